@@ -0,0 +1,208 @@
+use std::io::Cursor;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use rocket::data::ToByteUnit;
+use rocket::http::{Header, Status};
+use rocket::route::Outcome;
+use rocket::{Data, Request, Response};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::error::Error;
+use crate::format::OutputFormat;
+use crate::options::{OptionError, Options};
+use crate::{embed_qrcode, make_qrcode, render};
+
+/// Path of the JSON API.
+pub const PATH: &str = "/api/v1/qr";
+
+/// A request to the JSON API: the content, the options that the other routes
+/// read from the query string, and whether to wrap the output in an envelope.
+struct ApiRequest {
+    content: String,
+    options: Options,
+    envelope: bool,
+}
+
+/// The output and what it is, for clients that want JSON all the way.
+#[derive(Serialize)]
+struct Envelope<'a> {
+    format: &'static str,
+    content_type: &'a str,
+    contrast_ratio: f64,
+    /// The output, base64-encoded.
+    data: String,
+}
+
+/// One entry of the `errors` list of error responses.
+#[derive(Serialize)]
+pub struct ApiError {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<String>,
+    message: String,
+}
+
+impl ApiError {
+    /// Lists the invalid fields of `error`, or describes it without a field.
+    pub fn list(error: &Error) -> Vec<ApiError> {
+        let from_option = |e: &OptionError| ApiError {
+            field: Some(e.field.clone()),
+            message: e.message.clone(),
+        };
+        match error {
+            Error::Option(e) => vec![from_option(e)],
+            Error::Options(errors) => errors.iter().map(from_option).collect(),
+            Error::Internal(_) => vec![ApiError {
+                field: None,
+                message: "internal error".into(),
+            }],
+            e => vec![ApiError {
+                field: None,
+                message: e.to_string(),
+            }],
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ApiErrors {
+    errors: Vec<ApiError>,
+}
+
+/// Converts a JSON value to what `Options::set` takes: strings, and numbers
+/// and booleans as a convenience. Null stands for a missing field.
+pub fn scalar(key: &str, value: &Value) -> Result<Option<String>, OptionError> {
+    match value {
+        Value::Null => Ok(None),
+        Value::String(s) => Ok(Some(s.clone())),
+        Value::Number(n) => Ok(Some(n.to_string())),
+        Value::Bool(b) => Ok(Some(b.to_string())),
+        Value::Array(_) | Value::Object(_) => Err(OptionError::new(key, "expected a string, a number or a boolean")),
+    }
+}
+
+/// Applies an option given as a JSON field.
+pub fn set_option(options: &mut Options, key: &str, value: &Value) -> Result<(), OptionError> {
+    match scalar(key, value)? {
+        Some(value) => match options.set(key, &value)? {
+            true => Ok(()),
+            false => Err(OptionError::new(key, "unknown option")),
+        },
+        None => Ok(()),
+    }
+}
+
+/// Reads a request body such as `{"content": "hello", "ec": "H", "size": 300}`.
+/// Every field is checked, so that all the invalid ones are reported at once.
+fn parse_request(body: &str) -> Result<ApiRequest, Error> {
+    let fields: Map<String, Value> = match serde_json::from_str(body) {
+        Ok(fields) => fields,
+        Err(e) => return Err(OptionError::new("body", format!("expected a JSON object: {}", e)).into()),
+    };
+
+    let mut content = None;
+    let mut options = Options::default();
+    let mut envelope = false;
+    let mut errors = Vec::new();
+    for (key, value) in &fields {
+        let result = match key.as_str() {
+            "content" => scalar(key, value).map(|value| content = value),
+            "envelope" => match value {
+                Value::Null => Ok(()),
+                Value::Bool(b) => {
+                    envelope = *b;
+                    Ok(())
+                }
+                _ => Err(OptionError::new(key, "expected true or false")),
+            },
+            key => set_option(&mut options, key, value),
+        };
+        errors.extend(result.err());
+    }
+
+    let content = match content {
+        Some(content) if errors.is_empty() => content,
+        Some(_) => return Err(Error::Options(errors)),
+        None => {
+            errors.push(OptionError::new("content", "expected the text to encode"));
+            return Err(Error::Options(errors));
+        }
+    };
+    options.validate()?;
+
+    Ok(ApiRequest {
+        content,
+        options,
+        envelope,
+    })
+}
+
+/// Renders the error as JSON, with one entry per invalid field.
+pub fn make_error<'a>(error: Error) -> Outcome<'a> {
+    match error {
+        Error::Status(status) => return Outcome::Error(status),
+        Error::Internal(e) => {
+            eprintln!("Error: {}", e);
+            return Outcome::Error(Status::InternalServerError);
+        }
+        _ => {}
+    }
+
+    let body = serde_json::to_string(&ApiErrors {
+        errors: ApiError::list(&error),
+    })
+    .unwrap();
+    Outcome::Success(
+        Response::build()
+            .status(error.status())
+            .header(Header::new("Content-Type", "application/json"))
+            .sized_body(body.len(), Cursor::new(body))
+            .finalize(),
+    )
+}
+
+async fn generate(req: &'_ Request<'_>, body: Data<'_>) -> Result<(&'static str, Vec<u8>), Error> {
+    if !req.content_type().is_some_and(|content_type| content_type.is_json()) {
+        return Err(Error::Status(Status::UnsupportedMediaType));
+    }
+    let body = match body.open(2.megabytes()).into_string().await {
+        Ok(body) if body.is_complete() => body.into_inner(),
+        Ok(_) => return Err(Error::Status(Status::PayloadTooLarge)),
+        Err(_) => return Err(Error::Status(Status::BadRequest)),
+    };
+
+    let ApiRequest {
+        content,
+        options,
+        envelope,
+    } = parse_request(&body)?;
+
+    // Clients of the API say what they want, so there is no negotiation.
+    let format = options.format.unwrap_or(OutputFormat::PNG);
+    let code = make_qrcode(&content, format, &options)?;
+    let (content_type, code) = embed_qrcode(&content, format, &options, code)?;
+    if !envelope {
+        return Ok((content_type, code));
+    }
+
+    let envelope = Envelope {
+        format: format.name(),
+        content_type,
+        contrast_ratio: render::contrast_ratio(&options),
+        data: BASE64_STANDARD.encode(&code),
+    };
+    Ok(("application/json", serde_json::to_vec(&envelope).unwrap()))
+}
+
+/// Answers `POST /api/v1/qr`.
+pub async fn handle<'r>(req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+    match generate(req, data).await {
+        Ok((content_type, code)) => Outcome::Success(
+            Response::build()
+                .header(Header::new("Content-Type", content_type))
+                .sized_body(code.len(), Cursor::new(code))
+                .finalize(),
+        ),
+        Err(e) => make_error(e),
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use rocket::data::ToByteUnit;
+use rocket::http::{Header, Status};
+use rocket::route::Outcome;
+use rocket::{Data, Request, Response};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::api::{self, ApiError};
+use crate::error::Error;
+use crate::format::OutputFormat;
+use crate::options::{Embed, OptionError, Options};
+use crate::{make_qrcode, parse_query};
+
+/// Path of the batch endpoint.
+pub const PATH: &str = "/api/v1/batch";
+
+/// Name of the manifest in the archive.
+const MANIFEST: &str = "manifest.json";
+
+/// Longest id, which is also the file name without its extension.
+const MAX_ID_LENGTH: usize = 128;
+
+/// Limit on either side of each image in a batch, in pixels, well below the
+/// one of single requests, since the whole archive is held in memory.
+const MAX_DIMENSION: usize = 2048;
+
+/// How large batches may be. Read from `QQR_BATCH_MAX_ITEMS` and
+/// `QQR_BATCH_MAX_BYTES` when the server starts.
+#[derive(Clone, Copy)]
+pub struct Limits {
+    pub max_items: usize,
+    /// Largest request body.
+    pub max_bytes: u64,
+}
+
+impl Limits {
+    pub fn from_env() -> Self {
+        fn read<T: std::str::FromStr>(name: &str, default: T) -> T {
+            match std::env::var(name) {
+                Ok(value) => value.parse().unwrap_or_else(|_| panic!("{} must be a positive number", name)),
+                Err(_) => default,
+            }
+        }
+
+        Limits {
+            max_items: read("QQR_BATCH_MAX_ITEMS", 1000),
+            max_bytes: read("QQR_BATCH_MAX_BYTES", 10 * 1024 * 1024),
+        }
+    }
+}
+
+/// An item that passed validation and is ready to encode.
+pub struct Job {
+    pub index: usize,
+    pub id: String,
+    pub content: String,
+    pub format: OutputFormat,
+    pub options: Options,
+}
+
+/// What became of one item, in the order of the request.
+#[derive(Serialize)]
+struct Entry {
+    index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<ApiError>,
+}
+
+#[derive(Serialize)]
+struct Manifest {
+    succeeded: usize,
+    failed: usize,
+    items: Vec<Entry>,
+}
+
+/// Reads the items of a JSON body: an array of objects such as
+/// `{"id": "tag-1", "content": "hello", "options": {"ec": "H"}}`.
+fn parse_json(body: &str) -> Result<Vec<Map<String, Value>>, Error> {
+    serde_json::from_str(body)
+        .map_err(|e| OptionError::new("body", format!("expected an array of JSON objects: {}", e)).into())
+}
+
+/// Reads the items of a CSV body. The header names the columns: `id`,
+/// `content`, and any options, which apply where their cell is not empty.
+fn parse_csv(body: &str) -> Result<Vec<Map<String, Value>>, Error> {
+    let error = |e: csv::Error| Error::from(OptionError::new("body", format!("expected CSV with a header: {}", e)));
+    let mut reader = csv::Reader::from_reader(body.as_bytes());
+    let header = reader.headers().map_err(error)?.clone();
+
+    let mut items = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(error)?;
+        let mut item = Map::new();
+        let mut options = Map::new();
+        for (name, cell) in header.iter().zip(record.iter()) {
+            match name {
+                "id" | "content" => item.insert(name.into(), Value::String(cell.into())),
+                _ if cell.is_empty() => continue,
+                _ => options.insert(name.into(), Value::String(cell.into())),
+            };
+        }
+        item.insert("options".into(), Value::Object(options));
+        items.push(item);
+    }
+
+    Ok(items)
+}
+
+/// Checks that an id is usable as a file name.
+fn check_id(id: &str) -> Result<(), OptionError> {
+    let valid = id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+    if id.is_empty() || id.len() > MAX_ID_LENGTH || id.starts_with('.') || !valid {
+        return Err(OptionError::new(
+            "id",
+            format!("expected up to {} letters, digits, `-`, `_` or `.`, not starting with `.`", MAX_ID_LENGTH),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Turns an item into a job, starting from the options of the query string.
+pub fn parse_item(index: usize, item: &Map<String, Value>, defaults: &Options) -> Result<Job, Vec<OptionError>> {
+    let mut id = None;
+    let mut content = None;
+    let mut options = defaults.clone();
+    let mut errors = Vec::new();
+    for (key, value) in item {
+        let result = match key.as_str() {
+            "id" => api::scalar(key, value).map(|value| id = value),
+            "content" => api::scalar(key, value).map(|value| content = value),
+            "options" => match value {
+                Value::Null => Ok(()),
+                Value::Object(fields) => {
+                    for (key, value) in fields {
+                        errors.extend(api::set_option(&mut options, key, value).err());
+                    }
+                    Ok(())
+                }
+                _ => Err(OptionError::new(key, "expected an object")),
+            },
+            key => Err(OptionError::new(key, "unknown field; options go in `options`")),
+        };
+        errors.extend(result.err());
+    }
+
+    match &id {
+        Some(id) => errors.extend(check_id(id).err()),
+        None => errors.push(OptionError::new("id", "expected a file name")),
+    }
+    if content.is_none() {
+        errors.push(OptionError::new("content", "expected the text to encode"));
+    }
+    if options.embed != Embed::None {
+        errors.push(OptionError::new("embed", "not available in batches"));
+    }
+    if errors.is_empty() {
+        errors.extend(options.validate().err());
+    }
+
+    match (id, content) {
+        (Some(id), Some(content)) if errors.is_empty() => Ok(Job {
+            index,
+            id,
+            content,
+            format: options.format.unwrap_or(OutputFormat::PNG),
+            options,
+        }),
+        _ => Err(errors),
+    }
+}
+
+/// Runs `f` on every job, on as many threads as there are cores, and returns
+/// the results in the order of the jobs.
+pub fn run_all<T: Send>(jobs: &[Job], f: impl Fn(&Job) -> T + Sync) -> Vec<T> {
+    let results: Vec<_> = jobs.iter().map(|_| Mutex::new(None)).collect();
+    let next = AtomicUsize::new(0);
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get()).min(jobs.len());
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(job) = jobs.get(i) else { break };
+                *results[i].lock().unwrap() = Some(f(job));
+            });
+        }
+    });
+
+    results.into_iter().map(|result| result.into_inner().unwrap().unwrap()).collect()
+}
+
+/// Codes in the archive, in the order of the jobs, and what became of them.
+struct Archive {
+    zip: ZipWriter<Cursor<Vec<u8>>>,
+    /// File names of the jobs written so far, or why they failed.
+    written: Vec<Result<String, Error>>,
+}
+
+impl Archive {
+    fn add(&mut self, job: &Job, code: &[u8]) -> Result<String, Error> {
+        // These formats are compressed already.
+        let method = match job.format {
+            OutputFormat::PNG | OutputFormat::JPEG | OutputFormat::WebP | OutputFormat::GIF => {
+                CompressionMethod::Stored
+            }
+            _ => CompressionMethod::Deflated,
+        };
+        let file = format!("{}.{}", job.id, job.format.extension());
+        let options = SimpleFileOptions::default().compression_method(method);
+        self.zip.start_file(file.as_str(), options).map_err(|e| Error::Internal(Box::new(e)))?;
+        self.zip.write_all(code).map_err(|e| Error::Internal(Box::new(e)))?;
+        Ok(file)
+    }
+}
+
+/// Encodes every item and writes the archive, with the manifest last.
+fn build(items: Vec<Map<String, Value>>, defaults: &Options) -> Result<Vec<u8>, Error> {
+    let mut entries = Vec::with_capacity(items.len());
+    let mut jobs = Vec::new();
+    let mut ids = HashSet::new();
+    for (index, item) in items.iter().enumerate() {
+        let id = item.get("id").and_then(|id| api::scalar("id", id).ok().flatten());
+        let errors = match parse_item(index, item, defaults) {
+            Ok(job) if format!("{}.{}", job.id, job.format.extension()) == MANIFEST => {
+                vec![OptionError::new("id", "reserved for the manifest")]
+            }
+            Ok(mut job) if ids.insert(job.id.clone()) => {
+                job.options.max_dimension = MAX_DIMENSION;
+                jobs.push(job);
+                Vec::new()
+            }
+            Ok(_) => vec![OptionError::new("id", "duplicate")],
+            Err(errors) => errors,
+        };
+        entries.push(Entry {
+            index,
+            id,
+            file: None,
+            content_type: None,
+            errors: ApiError::list(&Error::Options(errors)),
+        });
+    }
+
+    // Codes go into the archive in the order of the jobs, as soon as those
+    // before them are in, so that only codes which finished early wait.
+    let codes: Vec<_> = jobs.iter().map(|_| Mutex::new(None)).collect();
+    let archive = Mutex::new(Archive {
+        zip: ZipWriter::new(Cursor::new(Vec::new())),
+        written: Vec::with_capacity(jobs.len()),
+    });
+    run_all(&jobs, |job| {
+        let i = jobs.binary_search_by_key(&job.index, |job| job.index).unwrap();
+        *codes[i].lock().unwrap() = Some(make_qrcode(&job.content, job.format, &job.options));
+
+        let mut archive = archive.lock().unwrap();
+        while let Some(code) = codes.get(archive.written.len()).and_then(|code| code.lock().unwrap().take()) {
+            let job = &jobs[archive.written.len()];
+            let result = code.and_then(|code| archive.add(job, &code));
+            archive.written.push(result);
+        }
+    });
+
+    let Archive { mut zip, written } = archive.into_inner().unwrap();
+    for (job, result) in jobs.iter().zip(written) {
+        let entry = &mut entries[job.index];
+        match result {
+            Ok(file) => {
+                entry.file = Some(file);
+                entry.content_type = Some(job.format.content_type());
+            }
+            Err(e) => {
+                if let Error::Internal(ref e) = e {
+                    eprintln!("Error: {}", e);
+                }
+                entry.errors = ApiError::list(&e);
+            }
+        }
+    }
+
+    let failed = entries.iter().filter(|entry| !entry.errors.is_empty()).count();
+    let manifest = Manifest {
+        succeeded: entries.len() - failed,
+        failed,
+        items: entries,
+    };
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file(MANIFEST, options).map_err(|e| Error::Internal(Box::new(e)))?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest).unwrap()).map_err(|e| Error::Internal(Box::new(e)))?;
+
+    let zip = zip.finish().map_err(|e| Error::Internal(Box::new(e)))?;
+    Ok(zip.into_inner())
+}
+
+/// Reads the items of a JSON or CSV request body, within the limits.
+pub async fn read_items(
+    req: &'_ Request<'_>,
+    body: Data<'_>,
+    limits: Limits,
+) -> Result<Vec<Map<String, Value>>, Error> {
+    let csv = match req.content_type() {
+        Some(content_type) if content_type.is_json() => false,
+        Some(content_type) if content_type.is_csv() => true,
+        _ => return Err(Error::Status(Status::UnsupportedMediaType)),
+    };
+    let body = match body.open(limits.max_bytes.bytes()).into_string().await {
+        Ok(body) if body.is_complete() => body.into_inner(),
+        Ok(_) => return Err(Error::Status(Status::PayloadTooLarge)),
+        Err(_) => return Err(Error::Status(Status::BadRequest)),
+    };
+
+    let items = match csv {
+        true => parse_csv(&body)?,
+        false => parse_json(&body)?,
+    };
+    if items.len() > limits.max_items {
+        return Err(OptionError::new("body", format!("too many items; the limit is {}", limits.max_items)).into());
+    }
+
+    Ok(items)
+}
+
+async fn generate(req: &'_ Request<'_>, body: Data<'_>, limits: Limits) -> Result<Vec<u8>, Error> {
+    // Options of the query string apply to every item, unless it overrides them.
+    let mut defaults = Options::default();
+    if let Some(rest) = parse_query(req, &mut defaults, true)? {
+        if let Some(key) = rest.first() {
+            let key = key.split('=').next().unwrap_or_default();
+            return Err(OptionError::new(key, "unknown option").into());
+        }
+    }
+
+    let items = read_items(req, body, limits).await?;
+
+    // Encoding is CPU-bound, so keep it off the async workers.
+    rocket::tokio::task::spawn_blocking(move || build(items, &defaults))
+        .await
+        .map_err(|e| Error::Internal(Box::new(e)))?
+}
+
+/// Answers `POST /api/v1/batch` with a ZIP archive of the codes and a
+/// manifest of what succeeded and what failed. The archive is built in
+/// memory and sent once every item is done, so images are limited to
+/// `MAX_DIMENSION` pixels on either side.
+pub async fn handle<'r>(req: &'r Request<'_>, data: Data<'r>, limits: Limits) -> Outcome<'r> {
+    match generate(req, data, limits).await {
+        Ok(zip) => Outcome::Success(
+            Response::build()
+                .header(Header::new("Content-Type", "application/zip"))
+                .header(Header::new("Content-Disposition", "attachment; filename=\"qr-codes.zip\""))
+                .sized_body(zip.len(), Cursor::new(zip))
+                .finalize(),
+        ),
+        Err(e) => api::make_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use zip::ZipArchive;
+
+    use super::*;
+
+    fn items(json: &str) -> Vec<Map<String, Value>> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn keeps_the_manifest_name_for_the_manifest() {
+        let body = r#"[
+            {"id": "manifest", "content": "x", "options": {"format": "json"}},
+            {"id": "b", "content": "y", "options": {"format": "svg"}},
+            {"id": "a", "content": "z"}
+        ]"#;
+        let zip = build(items(body), &Options::default()).unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(zip)).unwrap();
+
+        let names: Vec<_> = (0..archive.len()).map(|i| archive.by_index(i).unwrap().name().to_owned()).collect();
+        assert_eq!(names, ["b.svg", "a.png", MANIFEST]);
+        assert_eq!(archive.by_name("a.png").unwrap().compression(), CompressionMethod::Stored);
+        assert_eq!(archive.by_name("b.svg").unwrap().compression(), CompressionMethod::Deflated);
+
+        let mut manifest = String::new();
+        archive.by_name(MANIFEST).unwrap().read_to_string(&mut manifest).unwrap();
+        let manifest: Value = serde_json::from_str(&manifest).unwrap();
+        assert_eq!(manifest["succeeded"], 2);
+        assert_eq!(manifest["failed"], 1);
+        assert_eq!(manifest["items"][0]["errors"][0]["field"], "id");
+        assert_eq!(manifest["items"][0]["errors"][0]["message"], "reserved for the manifest");
+    }
+}
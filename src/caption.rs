@@ -0,0 +1,239 @@
+use std::sync::OnceLock;
+
+use ab_glyph::{Font, FontRef, Glyph, OutlineCurve, PxScale, ScaleFont};
+
+/// DejaVu Sans, embedded so that captions look the same wherever the server
+/// runs. See `fonts/LICENSE-DejaVu.txt`.
+static FONT_DATA: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+
+/// Font family of SVG captions, with fallbacks of similar width.
+pub const FONT_FAMILY: &str = "'DejaVu Sans', Verdana, sans-serif";
+
+/// Most lines a caption wraps to.
+const MAX_LINES: usize = 3;
+
+/// Font size, relative to the width of the image, that captions start at and
+/// shrink from when they do not fit.
+const SIZE_RATIO: f32 = 1.0 / 12.0;
+
+/// Smallest font size, relative to the width of the image.
+const MIN_SIZE_RATIO: f32 = 1.0 / 30.0;
+
+/// Smallest font size in pixels, whatever the width of the image.
+const MIN_SIZE: f32 = 8.0;
+
+/// Distance between baselines, relative to the font size.
+const LINE_HEIGHT: f32 = 1.2;
+
+fn font() -> &'static FontRef<'static> {
+    static FONT: OnceLock<FontRef<'static>> = OnceLock::new();
+    FONT.get_or_init(|| FontRef::try_from_slice(FONT_DATA).expect("embedded font is valid"))
+}
+
+/// Caption text broken into lines that fit the image, in pixels.
+pub struct Caption {
+    pub lines: Vec<String>,
+    /// Font size: the height of the em square.
+    pub size: f32,
+    /// Top of the band the caption takes, set when it is placed.
+    pub top: usize,
+    /// Height of the band, including space above and below the text.
+    pub height: usize,
+    /// Space between the edges of the image and the text.
+    padding: usize,
+}
+
+impl Caption {
+    /// Wraps `text` to fit `width` pixels, less `padding` on every side, in
+    /// at most three lines. The font shrinks until the text fits, and long
+    /// words are broken and the last line cut short when even the smallest
+    /// size is too large.
+    pub fn new(text: &str, width: usize, padding: usize) -> Self {
+        let available = width.saturating_sub(2 * padding).max(1) as f32;
+        let max_size = (width as f32 * SIZE_RATIO).max(MIN_SIZE);
+        let min_size = (width as f32 * MIN_SIZE_RATIO).max(MIN_SIZE);
+        let words: Vec<&str> = text.split_whitespace().collect();
+
+        let mut size = max_size;
+        let (lines, size) = loop {
+            let lines = wrap(&words, available, size);
+            let fits = lines.len() <= MAX_LINES && lines.iter().all(|line| text_width(line, size) <= available);
+            if fits {
+                break (lines, size);
+            }
+            if size <= min_size {
+                break (cut(&words, available, min_size), min_size);
+            }
+            size = (size * 0.9).max(min_size);
+        };
+
+        Caption {
+            height: (lines.len() as f32 * size * LINE_HEIGHT).ceil() as usize + 2 * padding,
+            lines,
+            size,
+            top: 0,
+            padding,
+        }
+    }
+
+    /// Width of each line in pixels, with where it starts so that it is
+    /// centred in `width`, and where its baseline is.
+    pub fn positions(&self, width: usize) -> impl Iterator<Item = (&str, f32, f32, f32)> + '_ {
+        let ascent = font().as_scaled(PxScale::from(self.size)).ascent();
+        let line_height = self.size * LINE_HEIGHT;
+        // Share the leading between the top and the bottom of each line.
+        let first = self.top as f32 + self.padding as f32 + (line_height - self.size) / 2.0 + ascent;
+        self.lines.iter().enumerate().map(move |(i, line)| {
+            let line_width = text_width(line, self.size);
+            (line.as_str(), line_width, (width as f32 - line_width) / 2.0, first + i as f32 * line_height)
+        })
+    }
+
+    /// Calls `plot` with the coverage of every pixel the text touches, from 0
+    /// to 1, in an image `width` pixels wide.
+    pub fn draw(&self, width: usize, mut plot: impl FnMut(u32, u32, f32)) {
+        for (line, _, left, baseline) in self.positions(width) {
+            draw_line(line, self.size, left, baseline, &mut plot);
+        }
+    }
+}
+
+/// Glyphs of `text` at `size`, with kerning, starting from `left` on the
+/// baseline at `baseline`.
+fn glyphs(text: &str, size: f32, left: f32, baseline: f32) -> Vec<Glyph> {
+    let scaled = font().as_scaled(PxScale::from(size));
+    let mut x = left;
+    let mut previous = None;
+    let mut glyphs = Vec::new();
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            x += scaled.kern(previous, id);
+        }
+        glyphs.push(id.with_scale_and_position(size, ab_glyph::point(x, baseline)));
+        x += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    glyphs
+}
+
+/// Calls `plot` with the coverage of every pixel a line of text touches, from
+/// 0 to 1.
+pub fn draw_line(text: &str, size: f32, left: f32, baseline: f32, mut plot: impl FnMut(u32, u32, f32)) {
+    for glyph in glyphs(text, size, left, baseline) {
+        let Some(outline) = font().outline_glyph(glyph) else { continue };
+        let bounds = outline.px_bounds();
+        outline.draw(|gx, gy, coverage| {
+            let (px, py) = (bounds.min.x as i64 + gx as i64, bounds.min.y as i64 + gy as i64);
+            if px >= 0 && py >= 0 {
+                plot(px as u32, py as u32, coverage);
+            }
+        });
+    }
+}
+
+/// Calls `curve` with the points of every line, quadratic and cubic Bézier
+/// curve outlining a line of text, in pixels from the top-left. Each contour
+/// starts where the previous curve did not end.
+pub fn outline_line(text: &str, size: f32, left: f32, baseline: f32, mut curve: impl FnMut(&[(f32, f32)])) {
+    let font = font();
+    let scaled = font.as_scaled(PxScale::from(size));
+    let (h_scale, v_scale) = (scaled.h_scale_factor(), scaled.v_scale_factor());
+    for glyph in glyphs(text, size, left, baseline) {
+        let Some(outline) = font.outline(glyph.id) else { continue };
+        // Outlines are in font units, with y pointing up.
+        let at = |p: ab_glyph::Point| (glyph.position.x + p.x * h_scale, glyph.position.y - p.y * v_scale);
+        for segment in outline.curves {
+            match segment {
+                OutlineCurve::Line(p0, p1) => curve(&[at(p0), at(p1)]),
+                OutlineCurve::Quad(p0, p1, p2) => curve(&[at(p0), at(p1), at(p2)]),
+                OutlineCurve::Cubic(p0, p1, p2, p3) => curve(&[at(p0), at(p1), at(p2), at(p3)]),
+            }
+        }
+    }
+}
+
+/// Fits `text` on a single line `width` pixels wide, shrinking the font from
+/// `max_size` down to `min_size`, then cutting the text short with an
+/// ellipsis. Returns the line and its font size.
+pub fn fit_line(text: &str, width: f32, max_size: f32, min_size: f32) -> (String, f32) {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut size = max_size.max(min_size);
+    while text_width(&text, size) > width {
+        if size <= min_size {
+            let mut line = text.clone();
+            while !line.is_empty() && text_width(&format!("{}…", line), size) > width {
+                line.pop();
+            }
+            return (format!("{}…", line.trim_end()), size);
+        }
+        size = (size * 0.9).max(min_size);
+    }
+    (text, size)
+}
+
+/// Distance from the centre of a line of text at `size` to its baseline,
+/// which centres the line between the ascender and the descender.
+pub fn baseline_offset(size: f32) -> f32 {
+    let scaled = font().as_scaled(PxScale::from(size));
+    (scaled.ascent() + scaled.descent()) / 2.0
+}
+
+/// Width of `text` in pixels at `size`, with kerning.
+pub fn text_width(text: &str, size: f32) -> f32 {
+    let scaled = font().as_scaled(PxScale::from(size));
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            width += scaled.kern(previous, id);
+        }
+        width += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+/// Greedily fills lines with whole words. A word wider than a line gets a
+/// line of its own.
+fn wrap(words: &[&str], width: f32, size: f32) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in words {
+        match lines.last_mut() {
+            Some(line) if text_width(&format!("{} {}", line, word), size) <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+    lines
+}
+
+/// Fills lines character by character, breaking words where they overflow,
+/// and ends the last line with an ellipsis if the text goes on.
+fn cut(words: &[&str], width: f32, size: f32) -> Vec<String> {
+    let text = words.join(" ");
+    let mut lines = vec![String::new()];
+    for c in text.chars() {
+        let full = lines.len() == MAX_LINES;
+        let line = lines.last_mut().unwrap();
+        line.push(c);
+        if text_width(line, size) <= width {
+            continue;
+        }
+
+        line.pop();
+        if full {
+            while !line.is_empty() && text_width(&format!("{}…", line), size) > width {
+                line.pop();
+            }
+            line.push('…');
+            break;
+        }
+        lines.push(if c == ' ' { String::new() } else { c.to_string() });
+    }
+
+    lines.iter().map(|line| line.trim().to_string()).collect()
+}
@@ -0,0 +1,383 @@
+/// An sRGB color with straight alpha. Values only ever reach the output
+/// through [`Color::hex`] and [`Color::opacity`], never as the user wrote
+/// them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+    pub const TRANSPARENT: Color = Color { r: 0, g: 0, b: 0, a: 0 };
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b, a: 255 }
+    }
+
+    /// Parses a hex color (`#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`, with or
+    /// without the `#`), an `rgb()` or `rgba()` function, a CSS color name, or
+    /// `transparent`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim().to_ascii_lowercase();
+
+        if value == "transparent" {
+            return Ok(Color::TRANSPARENT);
+        }
+
+        if let Ok(i) = NAMED_COLORS.binary_search_by_key(&value.as_str(), |&(name, _)| name) {
+            let [r, g, b] = NAMED_COLORS[i].1;
+            return Ok(Color::rgb(r, g, b));
+        }
+
+        if let Some(args) = value.strip_prefix("rgba(").or_else(|| value.strip_prefix("rgb(")) {
+            return match args.strip_suffix(')') {
+                Some(args) => parse_function(args),
+                None => Err("missing `)`".into()),
+            };
+        }
+
+        parse_hex(value.strip_prefix('#').unwrap_or(&value))
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        self.a == 255
+    }
+
+    pub fn is_gray(&self) -> bool {
+        self.r == self.g && self.g == self.b
+    }
+
+    /// The color as `#rrggbb`, without its alpha.
+    pub fn hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+
+    /// The alpha channel, between 0 and 1.
+    pub fn opacity(&self) -> f64 {
+        f64::from(self.a) / 255.0
+    }
+
+    /// Composites this color over an opaque `backdrop`.
+    pub fn over(&self, backdrop: Color) -> Color {
+        let mix = |c: u8, b: u8| (f64::from(c) * self.opacity() + f64::from(b) * (1.0 - self.opacity())).round() as u8;
+        Color::rgb(mix(self.r, backdrop.r), mix(self.g, backdrop.g), mix(self.b, backdrop.b))
+    }
+
+    /// WCAG 2 relative luminance, ignoring alpha.
+    pub fn luminance(&self) -> f64 {
+        let linear = |c: u8| {
+            let c = f64::from(c) / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * linear(self.r) + 0.7152 * linear(self.g) + 0.0722 * linear(self.b)
+    }
+}
+
+/// Composites `fg` and `bg` into the opaque colors they will actually look
+/// like. A translucent background is assumed to be displayed on white.
+pub fn flatten(fg: Color, bg: Color) -> (Color, Color) {
+    let bg = bg.over(Color::WHITE);
+    (fg.over(bg), bg)
+}
+
+/// Like `flatten`, but a fully transparent background stays transparent, to
+/// be left unpainted, and only `fg` is made opaque.
+pub fn flatten_opaque_fg(fg: Color, bg: Color) -> (Color, Color) {
+    match bg.a {
+        0 => (fg.over(Color::WHITE), bg),
+        _ => flatten(fg, bg),
+    }
+}
+
+/// WCAG 2 contrast ratio between `fg` drawn on `bg`, from 1 to 21.
+pub fn contrast_ratio(fg: Color, bg: Color) -> f64 {
+    let (fg, bg) = flatten(fg, bg);
+    let (light, dark) = match fg.luminance() > bg.luminance() {
+        true => (fg.luminance(), bg.luminance()),
+        false => (bg.luminance(), fg.luminance()),
+    };
+    (light + 0.05) / (dark + 0.05)
+}
+
+fn parse_hex(hex: &str) -> Result<Color, String> {
+    let error = || format!("`{}` is not a hex color, rgb() color or color name", hex);
+
+    if !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(error());
+    }
+
+    let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).unwrap();
+    let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+
+    match hex.len() {
+        3 | 4 => {
+            let a = if hex.len() == 4 { digit(3) * 17 } else { 255 };
+            Ok(Color { r: digit(0) * 17, g: digit(1) * 17, b: digit(2) * 17, a })
+        }
+        6 | 8 => {
+            let a = if hex.len() == 8 { byte(6) } else { 255 };
+            Ok(Color { r: byte(0), g: byte(2), b: byte(4), a })
+        }
+        _ => Err(error()),
+    }
+}
+
+/// Parses the arguments of `rgb()` and `rgba()`, in either the comma-separated
+/// or the space-separated syntax: `rgb(255, 0, 0, 0.5)` or `rgb(100% 0 0 / 50%)`.
+fn parse_function(args: &str) -> Result<Color, String> {
+    let args: Vec<&str> = args
+        .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+        .filter(|arg| !arg.is_empty())
+        .collect();
+
+    let channel = |arg: &str| -> Result<u8, String> {
+        let value = match arg.strip_suffix('%') {
+            Some(percent) => percent.parse::<f64>().map(|p| p / 100.0 * 255.0),
+            None => arg.parse::<f64>(),
+        };
+        match value {
+            Ok(v) if (0.0..=255.0).contains(&v) => Ok(v.round() as u8),
+            _ => Err(format!("`{}` is not a color channel between 0 and 255", arg)),
+        }
+    };
+
+    let alpha = |arg: &str| -> Result<u8, String> {
+        let value = match arg.strip_suffix('%') {
+            Some(percent) => percent.parse::<f64>().map(|p| p / 100.0),
+            None => arg.parse::<f64>(),
+        };
+        match value {
+            Ok(v) if (0.0..=1.0).contains(&v) => Ok((v * 255.0).round() as u8),
+            _ => Err(format!("`{}` is not an alpha value between 0 and 1", arg)),
+        }
+    };
+
+    match args[..] {
+        [r, g, b] => Ok(Color::rgb(channel(r)?, channel(g)?, channel(b)?)),
+        [r, g, b, a] => Ok(Color { r: channel(r)?, g: channel(g)?, b: channel(b)?, a: alpha(a)? }),
+        _ => Err("expected 3 color channels and an optional alpha".into()),
+    }
+}
+
+/// CSS named colors, sorted by name.
+const NAMED_COLORS: [(&str, [u8; 3]); 148] = [
+    ("aliceblue", [240, 248, 255]),
+    ("antiquewhite", [250, 235, 215]),
+    ("aqua", [0, 255, 255]),
+    ("aquamarine", [127, 255, 212]),
+    ("azure", [240, 255, 255]),
+    ("beige", [245, 245, 220]),
+    ("bisque", [255, 228, 196]),
+    ("black", [0, 0, 0]),
+    ("blanchedalmond", [255, 235, 205]),
+    ("blue", [0, 0, 255]),
+    ("blueviolet", [138, 43, 226]),
+    ("brown", [165, 42, 42]),
+    ("burlywood", [222, 184, 135]),
+    ("cadetblue", [95, 158, 160]),
+    ("chartreuse", [127, 255, 0]),
+    ("chocolate", [210, 105, 30]),
+    ("coral", [255, 127, 80]),
+    ("cornflowerblue", [100, 149, 237]),
+    ("cornsilk", [255, 248, 220]),
+    ("crimson", [220, 20, 60]),
+    ("cyan", [0, 255, 255]),
+    ("darkblue", [0, 0, 139]),
+    ("darkcyan", [0, 139, 139]),
+    ("darkgoldenrod", [184, 134, 11]),
+    ("darkgray", [169, 169, 169]),
+    ("darkgreen", [0, 100, 0]),
+    ("darkgrey", [169, 169, 169]),
+    ("darkkhaki", [189, 183, 107]),
+    ("darkmagenta", [139, 0, 139]),
+    ("darkolivegreen", [85, 107, 47]),
+    ("darkorange", [255, 140, 0]),
+    ("darkorchid", [153, 50, 204]),
+    ("darkred", [139, 0, 0]),
+    ("darksalmon", [233, 150, 122]),
+    ("darkseagreen", [143, 188, 143]),
+    ("darkslateblue", [72, 61, 139]),
+    ("darkslategray", [47, 79, 79]),
+    ("darkslategrey", [47, 79, 79]),
+    ("darkturquoise", [0, 206, 209]),
+    ("darkviolet", [148, 0, 211]),
+    ("deeppink", [255, 20, 147]),
+    ("deepskyblue", [0, 191, 255]),
+    ("dimgray", [105, 105, 105]),
+    ("dimgrey", [105, 105, 105]),
+    ("dodgerblue", [30, 144, 255]),
+    ("firebrick", [178, 34, 34]),
+    ("floralwhite", [255, 250, 240]),
+    ("forestgreen", [34, 139, 34]),
+    ("fuchsia", [255, 0, 255]),
+    ("gainsboro", [220, 220, 220]),
+    ("ghostwhite", [248, 248, 255]),
+    ("gold", [255, 215, 0]),
+    ("goldenrod", [218, 165, 32]),
+    ("gray", [128, 128, 128]),
+    ("green", [0, 128, 0]),
+    ("greenyellow", [173, 255, 47]),
+    ("grey", [128, 128, 128]),
+    ("honeydew", [240, 255, 240]),
+    ("hotpink", [255, 105, 180]),
+    ("indianred", [205, 92, 92]),
+    ("indigo", [75, 0, 130]),
+    ("ivory", [255, 255, 240]),
+    ("khaki", [240, 230, 140]),
+    ("lavender", [230, 230, 250]),
+    ("lavenderblush", [255, 240, 245]),
+    ("lawngreen", [124, 252, 0]),
+    ("lemonchiffon", [255, 250, 205]),
+    ("lightblue", [173, 216, 230]),
+    ("lightcoral", [240, 128, 128]),
+    ("lightcyan", [224, 255, 255]),
+    ("lightgoldenrodyellow", [250, 250, 210]),
+    ("lightgray", [211, 211, 211]),
+    ("lightgreen", [144, 238, 144]),
+    ("lightgrey", [211, 211, 211]),
+    ("lightpink", [255, 182, 193]),
+    ("lightsalmon", [255, 160, 122]),
+    ("lightseagreen", [32, 178, 170]),
+    ("lightskyblue", [135, 206, 250]),
+    ("lightslategray", [119, 136, 153]),
+    ("lightslategrey", [119, 136, 153]),
+    ("lightsteelblue", [176, 196, 222]),
+    ("lightyellow", [255, 255, 224]),
+    ("lime", [0, 255, 0]),
+    ("limegreen", [50, 205, 50]),
+    ("linen", [250, 240, 230]),
+    ("magenta", [255, 0, 255]),
+    ("maroon", [128, 0, 0]),
+    ("mediumaquamarine", [102, 205, 170]),
+    ("mediumblue", [0, 0, 205]),
+    ("mediumorchid", [186, 85, 211]),
+    ("mediumpurple", [147, 112, 219]),
+    ("mediumseagreen", [60, 179, 113]),
+    ("mediumslateblue", [123, 104, 238]),
+    ("mediumspringgreen", [0, 250, 154]),
+    ("mediumturquoise", [72, 209, 204]),
+    ("mediumvioletred", [199, 21, 133]),
+    ("midnightblue", [25, 25, 112]),
+    ("mintcream", [245, 255, 250]),
+    ("mistyrose", [255, 228, 225]),
+    ("moccasin", [255, 228, 181]),
+    ("navajowhite", [255, 222, 173]),
+    ("navy", [0, 0, 128]),
+    ("oldlace", [253, 245, 230]),
+    ("olive", [128, 128, 0]),
+    ("olivedrab", [107, 142, 35]),
+    ("orange", [255, 165, 0]),
+    ("orangered", [255, 69, 0]),
+    ("orchid", [218, 112, 214]),
+    ("palegoldenrod", [238, 232, 170]),
+    ("palegreen", [152, 251, 152]),
+    ("paleturquoise", [175, 238, 238]),
+    ("palevioletred", [219, 112, 147]),
+    ("papayawhip", [255, 239, 213]),
+    ("peachpuff", [255, 218, 185]),
+    ("peru", [205, 133, 63]),
+    ("pink", [255, 192, 203]),
+    ("plum", [221, 160, 221]),
+    ("powderblue", [176, 224, 230]),
+    ("purple", [128, 0, 128]),
+    ("rebeccapurple", [102, 51, 153]),
+    ("red", [255, 0, 0]),
+    ("rosybrown", [188, 143, 143]),
+    ("royalblue", [65, 105, 225]),
+    ("saddlebrown", [139, 69, 19]),
+    ("salmon", [250, 128, 114]),
+    ("sandybrown", [244, 164, 96]),
+    ("seagreen", [46, 139, 87]),
+    ("seashell", [255, 245, 238]),
+    ("sienna", [160, 82, 45]),
+    ("silver", [192, 192, 192]),
+    ("skyblue", [135, 206, 235]),
+    ("slateblue", [106, 90, 205]),
+    ("slategray", [112, 128, 144]),
+    ("slategrey", [112, 128, 144]),
+    ("snow", [255, 250, 250]),
+    ("springgreen", [0, 255, 127]),
+    ("steelblue", [70, 130, 180]),
+    ("tan", [210, 180, 140]),
+    ("teal", [0, 128, 128]),
+    ("thistle", [216, 191, 216]),
+    ("tomato", [255, 99, 71]),
+    ("turquoise", [64, 224, 208]),
+    ("violet", [238, 130, 238]),
+    ("wheat", [245, 222, 179]),
+    ("white", [255, 255, 255]),
+    ("whitesmoke", [245, 245, 245]),
+    ("yellow", [255, 255, 0]),
+    ("yellowgreen", [154, 205, 50]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    #[test]
+    fn parses_hex() {
+        assert_eq!(Color::parse("#ff8000"), Ok(Color::rgb(255, 128, 0)));
+        assert_eq!(Color::parse("FF8000"), Ok(Color::rgb(255, 128, 0)));
+        assert_eq!(Color::parse("#f80"), Ok(Color::rgb(255, 136, 0)));
+        assert_eq!(Color::parse("#f808"), Ok(rgba(255, 136, 0, 136)));
+        assert_eq!(Color::parse(" #ff800080 "), Ok(rgba(255, 128, 0, 128)));
+
+        for invalid in ["", "#", "#ff", "#fffff", "#fffffffff", "#ggg", "#ff80é0"] {
+            assert!(Color::parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn parses_functions() {
+        assert_eq!(Color::parse("rgb(255, 128, 0)"), Ok(Color::rgb(255, 128, 0)));
+        assert_eq!(Color::parse("RGB(255 128 0)"), Ok(Color::rgb(255, 128, 0)));
+        assert_eq!(Color::parse("rgb(100%, 50%, 0%)"), Ok(Color::rgb(255, 128, 0)));
+        assert_eq!(Color::parse("rgba(255, 128, 0, 0.5)"), Ok(rgba(255, 128, 0, 128)));
+        assert_eq!(Color::parse("rgb(255 128 0 / 25%)"), Ok(rgba(255, 128, 0, 64)));
+        assert_eq!(Color::parse("rgb(0.5, 0, 0)"), Ok(Color::rgb(1, 0, 0)));
+
+        for invalid in [
+            "rgb(255, 128, 0",
+            "rgb()",
+            "rgb(255, 128)",
+            "rgb(1, 2, 3, 4, 5)",
+            "rgb(256, 0, 0)",
+            "rgb(-1, 0, 0)",
+            "rgb(101%, 0, 0)",
+            "rgb(0, 0, 0, 1.5)",
+            "rgb(0, 0, 0, 200%)",
+            "rgb(red, 0, 0)",
+        ] {
+            assert!(Color::parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn parses_names() {
+        assert_eq!(Color::parse("Transparent"), Ok(Color::TRANSPARENT));
+        assert_eq!(Color::parse("rebeccapurple"), Ok(Color::rgb(102, 51, 153)));
+        assert_eq!(Color::parse(" WHITE "), Ok(Color::WHITE));
+        assert!(Color::parse("notacolor").is_err());
+    }
+
+    #[test]
+    fn names_are_sorted() {
+        assert!(NAMED_COLORS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        for (name, [r, g, b]) in NAMED_COLORS {
+            assert_eq!(Color::parse(name), Ok(Color::rgb(r, g, b)), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt::Write;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde::Serialize;
+
+use crate::format::OutputFormat;
+
+/// Characters left as they are in SVG data URIs. Everything else is
+/// percent-encoded, which keeps the markup mostly readable and the URI safe
+/// to put in an attribute.
+fn is_uri_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-._~!$&'()*+,;=:@/?".contains(c)
+}
+
+/// A base64 `data:` URI.
+pub fn data_uri_of(content_type: &str, bytes: &[u8]) -> String {
+    format!("data:{};base64,{}", content_type, BASE64_STANDARD.encode(bytes))
+}
+
+/// Builds a `data:` URI for rendered output. SVG stays text, and everything
+/// else is base64-encoded.
+pub fn data_uri(format: OutputFormat, bytes: &[u8]) -> String {
+    let content_type = format.content_type();
+    match format {
+        OutputFormat::SVG => {
+            let svg = String::from_utf8_lossy(bytes);
+            let mut uri = format!("data:{},", content_type);
+            for c in svg.chars() {
+                if is_uri_safe(c) {
+                    uri.push(c);
+                } else {
+                    for b in c.encode_utf8(&mut [0; 4]).bytes() {
+                        write!(uri, "%{:02X}", b).unwrap();
+                    }
+                }
+            }
+            uri
+        }
+        _ => data_uri_of(content_type, bytes),
+    }
+}
+
+#[derive(Serialize)]
+struct DataUri<'a> {
+    content_type: &'a str,
+    data_uri: String,
+}
+
+/// A JSON object holding the data URI and its content type.
+pub fn json(format: OutputFormat, bytes: &[u8]) -> String {
+    let object = DataUri {
+        content_type: format.content_type(),
+        data_uri: data_uri(format, bytes),
+    };
+    serde_json::to_string(&object).unwrap()
+}
+
+/// An `<img>` tag showing the output inline.
+pub fn img(format: OutputFormat, bytes: &[u8], alt: &str) -> String {
+    format!(r#"<img src="{}" alt="{}">"#, escape_html(&data_uri(format, bytes)), escape_html(alt))
+}
+
+/// Escapes text for HTML and XML attributes and content.
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
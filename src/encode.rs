@@ -0,0 +1,90 @@
+use qrcode::types::{Mode, QrError};
+use qrcode::{EcLevel, QrCode, Version};
+
+use crate::error::Error;
+use crate::options::Options;
+use crate::rmqr;
+use crate::symbol::{Symbol, SymbolKind};
+
+/// Encodes `content` in the smallest version allowed by `options`, failing
+/// with a capacity error if even the largest allowed version is too small.
+/// A logo covers part of the symbol, so it always gets the highest level of
+/// error correction.
+pub fn encode(content: &str, options: &Options) -> Result<Symbol, Error> {
+    let kind = options.symbol;
+    let ec = match options.logo {
+        Some(_) => EcLevel::H,
+        None => options.ec,
+    };
+    let (min, max) = match options.version {
+        Some(version) => (version, version),
+        None => (options.min_version.unwrap_or(1), options.max_version.unwrap_or(kind.versions())),
+    };
+
+    match kind {
+        SymbolKind::QR if (min, max) == (1, 40) => match QrCode::with_error_correction_level(content, ec) {
+            Err(QrError::DataTooLong) => Err(capacity_error(content, kind, max, ec)),
+            code => Ok(Symbol::from_qrcode(kind, &code?)),
+        },
+        SymbolKind::QR | SymbolKind::Micro => {
+            for version in min..=max {
+                let version = match kind {
+                    SymbolKind::Micro => Version::Micro(version),
+                    _ => Version::Normal(version),
+                };
+
+                // Small Micro QR versions lack some levels and some modes.
+                match QrCode::with_version(content, version, ec) {
+                    Err(QrError::DataTooLong | QrError::InvalidVersion | QrError::UnsupportedCharacterSet) => continue,
+                    code => return Ok(Symbol::from_qrcode(kind, &code?)),
+                }
+            }
+
+            Err(capacity_error(content, kind, max, ec))
+        }
+        SymbolKind::RMQR => match rmqr::encode(content.as_bytes(), ec, min, max) {
+            Some(symbol) => Ok(symbol),
+            None => Err(capacity_error(content, kind, max, ec)),
+        },
+    }
+}
+
+/// Describes how much `version` can hold, in data bits and in bytes of
+/// byte-mode content, which is what most users will compare against.
+fn capacity_error(content: &str, kind: SymbolKind, version: i16, ec: EcLevel) -> Error {
+    let (bits, bytes) = match kind {
+        SymbolKind::QR | SymbolKind::Micro => {
+            let qr_version = match kind {
+                SymbolKind::Micro => Version::Micro(version),
+                _ => Version::Normal(version),
+            };
+            let bits = match qrcode::bits::Bits::new(qr_version).max_len(ec) {
+                Ok(bits) => bits,
+                Err(_) => {
+                    return Error::Capacity(format!(
+                        "version {} does not support error-correction level {:?}",
+                        kind.version_name(version),
+                        ec
+                    ))
+                }
+            };
+            let header = qr_version.mode_bits_count() + Mode::Byte.length_bits_count(qr_version);
+            match qr_version {
+                // M1 and M2 have no byte mode at all.
+                Version::Micro(1 | 2) => (bits, 0),
+                _ => (bits, bits.saturating_sub(header) / 8),
+            }
+        }
+        SymbolKind::RMQR => rmqr::capacity(version, ec),
+    };
+
+    Error::Capacity(format!(
+        "content is {} bytes, which does not fit in version {} at error-correction level {:?}: \
+         it holds {} data bits, or at most {} bytes of binary content",
+        content.len(),
+        kind.version_name(version),
+        ec,
+        bits,
+        bytes
+    ))
+}
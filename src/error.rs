@@ -0,0 +1,84 @@
+use std::fmt;
+
+use rocket::http::Status;
+
+use crate::options::OptionError;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request itself is unusable; answered with a bare status.
+    Status(Status),
+    /// An option has an invalid value.
+    Option(OptionError),
+    /// Several options have invalid values.
+    Options(Vec<OptionError>),
+    /// The content does not fit in the requested symbol.
+    Capacity(String),
+    /// The colors would make the symbol hard or impossible to scan.
+    Contrast(String),
+    /// The symbol cannot be drawn within the size limits.
+    Size(String),
+    /// The rendered symbol did not decode back to its content.
+    Unreadable(String),
+    /// None of the types in the `Accept` header can be produced.
+    NotAcceptable(String),
+    /// Anything else. Logged, never shown to the client.
+    Internal(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl Error {
+    pub fn status(&self) -> Status {
+        match self {
+            Error::Status(status) => *status,
+            Error::Option(_) | Error::Options(_) => Status::BadRequest,
+            Error::Capacity(_) | Error::Contrast(_) | Error::Size(_) | Error::Unreadable(_) => {
+                Status::UnprocessableEntity
+            }
+            Error::NotAcceptable(_) => Status::NotAcceptable,
+            Error::Internal(_) => Status::InternalServerError,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Status(status) => write!(f, "{}", status),
+            Error::Option(e) => write!(f, "{}", e),
+            Error::Options(errors) => {
+                let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", messages.join("; "))
+            }
+            Error::Capacity(message)
+            | Error::Contrast(message)
+            | Error::Size(message)
+            | Error::Unreadable(message)
+            | Error::NotAcceptable(message) => write!(f, "{}", message),
+            Error::Internal(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<OptionError> for Error {
+    fn from(e: OptionError) -> Self {
+        Error::Option(e)
+    }
+}
+
+impl From<qrcode::types::QrError> for Error {
+    fn from(e: qrcode::types::QrError) -> Self {
+        Error::Internal(Box::new(e))
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(e: image::ImageError) -> Self {
+        Error::Internal(Box::new(e))
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(e: png::EncodingError) -> Self {
+        Error::Internal(Box::new(e))
+    }
+}
@@ -0,0 +1,271 @@
+use std::cmp::Reverse;
+
+/// Output formats, selected with `format`, a path extension, or the `Accept`
+/// header, in that order of precedence.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    PNG,
+    SVG,
+    JPEG,
+    WebP,
+    GIF,
+    BMP,
+    PDF,
+    EPS,
+    /// Unicode half blocks.
+    Text,
+    ASCII,
+    ANSI,
+    Sixel,
+    Kitty,
+    /// The module matrix, for clients that draw the symbol themselves.
+    JSON,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 14] = [
+        OutputFormat::PNG,
+        OutputFormat::SVG,
+        OutputFormat::JPEG,
+        OutputFormat::WebP,
+        OutputFormat::GIF,
+        OutputFormat::BMP,
+        OutputFormat::PDF,
+        OutputFormat::EPS,
+        OutputFormat::Text,
+        OutputFormat::ASCII,
+        OutputFormat::ANSI,
+        OutputFormat::Sixel,
+        OutputFormat::Kitty,
+        OutputFormat::JSON,
+    ];
+
+    /// Name used by `format` and by path extensions.
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::PNG => "png",
+            OutputFormat::SVG => "svg",
+            OutputFormat::JPEG => "jpeg",
+            OutputFormat::WebP => "webp",
+            OutputFormat::GIF => "gif",
+            OutputFormat::BMP => "bmp",
+            OutputFormat::PDF => "pdf",
+            OutputFormat::EPS => "eps",
+            OutputFormat::Text => "text",
+            OutputFormat::ASCII => "ascii",
+            OutputFormat::ANSI => "ansi",
+            OutputFormat::Sixel => "sixel",
+            OutputFormat::Kitty => "kitty",
+            OutputFormat::JSON => "json",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            OutputFormat::PNG => "image/png",
+            OutputFormat::SVG => "image/svg+xml",
+            OutputFormat::JPEG => "image/jpeg",
+            OutputFormat::WebP => "image/webp",
+            OutputFormat::GIF => "image/gif",
+            OutputFormat::BMP => "image/bmp",
+            OutputFormat::PDF => "application/pdf",
+            OutputFormat::EPS => "application/postscript",
+            OutputFormat::Text | OutputFormat::ASCII | OutputFormat::ANSI => "text/plain; charset=utf-8",
+            OutputFormat::Sixel => "image/x-sixel",
+            OutputFormat::Kitty => "application/x-kitty-graphics",
+            OutputFormat::JSON => "application/json",
+        }
+    }
+
+    /// File name extension, for archives.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Text | OutputFormat::ASCII | OutputFormat::ANSI => "txt",
+            format => format.name(),
+        }
+    }
+
+    /// Whether browsers display this format in an `<img>`.
+    pub fn is_web_image(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::PNG
+                | OutputFormat::SVG
+                | OutputFormat::JPEG
+                | OutputFormat::WebP
+                | OutputFormat::GIF
+                | OutputFormat::BMP
+        )
+    }
+
+    /// Whether this format is an image or a document, as opposed to text or
+    /// data for terminals and scripts.
+    pub fn is_file(self) -> bool {
+        self.is_web_image() || matches!(self, OutputFormat::PDF | OutputFormat::EPS)
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("jpg") {
+            return Some(OutputFormat::JPEG);
+        }
+        Self::ALL.into_iter().find(|format| format.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Splits the extension of an image or document format off `path`. Text
+    /// and data formats are left alone, since paths such as `data.json` are
+    /// common in URLs. Only one is removed, so content that really ends in
+    /// `.png` can be sent as `.png.png`.
+    pub fn strip_extension(path: &str) -> Option<(&str, Self)> {
+        let (rest, extension) = path.rsplit_once('.')?;
+        Some((rest, Self::from_name(extension).filter(|format| format.is_file())?))
+    }
+}
+
+/// One media range of an `Accept` header, such as `image/*;q=0.8`.
+struct MediaRange<'a> {
+    top: &'a str,
+    sub: &'a str,
+    q: f32,
+}
+
+impl<'a> MediaRange<'a> {
+    /// Parses a media range, skipping any that are malformed rather than
+    /// failing the whole header.
+    fn parse(range: &'a str) -> Option<Self> {
+        let mut parts = range.split(';').map(str::trim);
+        let (top, sub) = parts.next()?.split_once('/')?;
+        if top.is_empty() || sub.is_empty() || (top == "*" && sub != "*") {
+            return None;
+        }
+
+        let mut q = 1.0;
+        for param in parts {
+            if let Some((name, value)) = param.split_once('=') {
+                if name.trim().eq_ignore_ascii_case("q") {
+                    q = value.trim().parse::<f32>().ok().filter(|q| (0.0..=1.0).contains(q))?;
+                }
+            }
+        }
+
+        Some(MediaRange { top, sub, q })
+    }
+
+    /// How specifically this range matches `content_type`: 2 for an exact
+    /// match, 1 for `type/*` and 0 for `*/*`. Parameters are ignored.
+    fn specificity(&self, content_type: &str) -> Option<u8> {
+        let (top, sub) = content_type.split(';').next()?.split_once('/')?;
+        match (self.top, self.sub) {
+            ("*", "*") => Some(0),
+            (t, "*") if t.eq_ignore_ascii_case(top) => Some(1),
+            (t, s) if t.eq_ignore_ascii_case(top) && s.eq_ignore_ascii_case(sub) => Some(2),
+            _ => None,
+        }
+    }
+}
+
+/// Picks the format with the highest quality in the `Accept` header values,
+/// using the most specific range that matches each format. Ties go to the
+/// first format in [`OutputFormat::ALL`]. Returns `None` when every format has
+/// a quality of zero or matches no range.
+pub fn negotiate<'a>(accept: impl Iterator<Item = &'a str>) -> Option<OutputFormat> {
+    let ranges: Vec<_> = accept.flat_map(|value| value.split(',')).filter_map(MediaRange::parse).collect();
+    if ranges.is_empty() {
+        return Some(OutputFormat::ALL[0]);
+    }
+
+    let mut best: Option<(OutputFormat, f32)> = None;
+    for format in OutputFormat::ALL {
+        // The first of the most specific ranges wins.
+        let matching = ranges
+            .iter()
+            .filter_map(|range| Some((range.specificity(format.content_type())?, range.q)))
+            .min_by_key(|&(specificity, _)| Reverse(specificity));
+
+        match matching {
+            Some((_, q)) if q > 0.0 && best.is_none_or(|(_, best)| q > best) => best = Some((format, q)),
+            _ => {}
+        }
+    }
+
+    best.map(|(format, _)| format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_image_and_document_extensions() {
+        assert_eq!(OutputFormat::strip_extension("hello.svg"), Some(("hello", OutputFormat::SVG)));
+        assert_eq!(OutputFormat::strip_extension("hello.JPG"), Some(("hello", OutputFormat::JPEG)));
+        assert_eq!(OutputFormat::strip_extension("logo.png.png"), Some(("logo.png", OutputFormat::PNG)));
+        assert_eq!(OutputFormat::strip_extension("a/report.pdf"), Some(("a/report", OutputFormat::PDF)));
+
+        for path in ["https://api.example.com/data.json", "notes.text", "art.ascii", "x.kitty", "hello", "v1.2"] {
+            assert_eq!(OutputFormat::strip_extension(path), None, "{:?}", path);
+        }
+    }
+
+    fn accept(header: &str) -> Option<OutputFormat> {
+        negotiate([header].into_iter())
+    }
+
+    #[test]
+    fn parses_media_ranges() {
+        let range = MediaRange::parse(" image/* ; Q=0.5 ").unwrap();
+        assert_eq!((range.top, range.sub, range.q), ("image", "*", 0.5));
+        assert_eq!(MediaRange::parse("image/png;charset=x").unwrap().q, 1.0);
+
+        for malformed in ["", "image", "image/", "/png", "*/png", "image/png;q=2", "image/png;q=-1", "image/png;q=x"] {
+            assert!(MediaRange::parse(malformed).is_none(), "{:?}", malformed);
+        }
+    }
+
+    #[test]
+    fn ranks_ranges_by_specificity() {
+        let specificity = |range: &str, content_type: &str| MediaRange::parse(range)?.specificity(content_type);
+        assert_eq!(specificity("image/png", "image/png"), Some(2));
+        assert_eq!(specificity("TEXT/Plain", "text/plain; charset=utf-8"), Some(2));
+        assert_eq!(specificity("image/*", "image/png"), Some(1));
+        assert_eq!(specificity("*/*", "application/pdf"), Some(0));
+        assert_eq!(specificity("image/*", "application/pdf"), None);
+        assert_eq!(specificity("image/png", "image/gif"), None);
+    }
+
+    #[test]
+    fn defaults_without_usable_ranges() {
+        assert_eq!(negotiate(std::iter::empty()), Some(OutputFormat::PNG));
+        assert_eq!(accept(""), Some(OutputFormat::PNG));
+        assert_eq!(accept("garbage, */png"), Some(OutputFormat::PNG));
+    }
+
+    #[test]
+    fn picks_the_highest_quality() {
+        assert_eq!(accept("image/svg+xml"), Some(OutputFormat::SVG));
+        assert_eq!(accept("image/*;q=0.5, application/pdf"), Some(OutputFormat::PDF));
+        assert_eq!(negotiate(["image/png;q=0.5", "image/gif"].into_iter()), Some(OutputFormat::GIF));
+    }
+
+    #[test]
+    fn breaks_ties_in_format_order() {
+        assert_eq!(accept("image/*"), Some(OutputFormat::PNG));
+        assert_eq!(accept("application/pdf;q=0.8, image/svg+xml;q=0.8"), Some(OutputFormat::SVG));
+        assert_eq!(accept("text/plain"), Some(OutputFormat::Text));
+    }
+
+    #[test]
+    fn uses_the_most_specific_range() {
+        assert_eq!(accept("image/png;q=0.1, */*"), Some(OutputFormat::SVG));
+        assert_eq!(accept("*/*;q=0.1, image/*;q=0.2, image/webp"), Some(OutputFormat::WebP));
+        // The first of equally specific ranges applies.
+        assert_eq!(accept("image/png;q=0.1, image/png, image/gif;q=0.5"), Some(OutputFormat::GIF));
+    }
+
+    #[test]
+    fn excludes_zero_quality() {
+        assert_eq!(accept("image/png;q=0"), None);
+        assert_eq!(accept("image/*, image/png;q=0"), Some(OutputFormat::SVG));
+        assert_eq!(accept("*/*;q=0"), None);
+    }
+}
@@ -0,0 +1,311 @@
+use std::fmt::Write;
+
+use crate::caption;
+use crate::options::FrameKind;
+use crate::pdf;
+
+/// Samples per pixel along each axis on the edges of outlines.
+const SAMPLES: usize = 4;
+
+/// Height of the area the text goes in, relative to the width of the symbol
+/// and its quiet zone.
+const BANNER_RATIO: f64 = 0.22;
+
+/// Largest and smallest font sizes, relative to the height of the banner.
+const MAX_TEXT_RATIO: f32 = 0.5;
+const MIN_TEXT_RATIO: f32 = 0.25;
+
+/// Smallest font size in pixels, whatever the size of the banner.
+const MIN_TEXT_SIZE: f32 = 8.0;
+
+/// Distance from a quarter circle to the control points of the cubic Bézier
+/// curve that approximates it, relative to its radius.
+const KAPPA: f64 = 0.5523;
+
+/// A convex area the frame fills, in pixels from the top-left of the image.
+pub enum Outline {
+    /// A rectangle given by its left, top, width and height, whose corners
+    /// are rounded by a radius.
+    Rect(f64, f64, f64, f64, f64),
+    Polygon(Vec<(f64, f64)>),
+}
+
+/// What an outline is filled with. Outlines of the same fill never overlap,
+/// so that they can go in a single path.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Fill {
+    Frame,
+    /// A hole in the frame, showing the background under the symbol and its
+    /// quiet zone.
+    Background,
+}
+
+/// Steps of a path, for both SVG and PDF.
+enum Segment {
+    Move(f64, f64),
+    Line(f64, f64),
+    Cubic([f64; 6]),
+    Close,
+}
+
+impl Outline {
+    /// Left, top, right and bottom edges.
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        match self {
+            Outline::Rect(left, top, width, height, _) => (*left, *top, left + width, top + height),
+            Outline::Polygon(points) => points.iter().fold(
+                (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+                |(l, t, r, b), &(x, y)| (l.min(x), t.min(y), r.max(x), b.max(y)),
+            ),
+        }
+    }
+
+    fn contains(&self, x: f64, y: f64) -> bool {
+        match *self {
+            Outline::Rect(left, top, width, height, r) => {
+                let (dx, dy) = ((x - left).min(left + width - x), (y - top).min(top + height - y));
+                dx >= 0.0 && dy >= 0.0 && (dx >= r || dy >= r || (r - dx).powi(2) + (r - dy).powi(2) <= r * r)
+            }
+            Outline::Polygon(ref points) => {
+                // Inside a convex polygon, every edge turns the same way.
+                let sides = points.iter().zip(points.iter().cycle().skip(1)).map(|(&(x0, y0), &(x1, y1))| {
+                    (x1 - x0) * (y - y0) - (y1 - y0) * (x - x0)
+                });
+                let (mut left, mut right) = (false, false);
+                for side in sides {
+                    left |= side < 0.0;
+                    right |= side > 0.0;
+                }
+                !(left && right)
+            }
+        }
+    }
+
+    /// Calls `plot` with the share of every pixel the outline covers, from 0
+    /// to 1. Pixels whose corners are all inside are fully covered, since the
+    /// outline is convex, and only the others are sampled.
+    pub fn draw(&self, mut plot: impl FnMut(u32, u32, f32)) {
+        let (left, top, right, bottom) = self.bounds();
+        for y in top.max(0.0).floor() as u32..bottom.ceil() as u32 {
+            for x in left.max(0.0).floor() as u32..right.ceil() as u32 {
+                let (px, py) = (x as f64, y as f64);
+                let corners = [(px, py), (px + 1.0, py), (px, py + 1.0), (px + 1.0, py + 1.0)];
+                let coverage = if corners.iter().all(|&(cx, cy)| self.contains(cx, cy)) {
+                    1.0
+                } else {
+                    let point = |i: usize| (i as f64 + 0.5) / SAMPLES as f64;
+                    let count = (0..SAMPLES * SAMPLES)
+                        .filter(|i| self.contains(px + point(i % SAMPLES), py + point(i / SAMPLES)))
+                        .count();
+                    count as f32 / (SAMPLES * SAMPLES) as f32
+                };
+                if coverage > 0.0 {
+                    plot(x, y, coverage);
+                }
+            }
+        }
+    }
+
+    fn segments(&self) -> Vec<Segment> {
+        match *self {
+            Outline::Rect(left, top, width, height, r) => {
+                let (right, bottom, k) = (left + width, top + height, r * (1.0 - KAPPA));
+                let mut segments = vec![Segment::Move(left + r, top), Segment::Line(right - r, top)];
+                if r > 0.0 {
+                    segments.push(Segment::Cubic([right - k, top, right, top + k, right, top + r]));
+                }
+                segments.push(Segment::Line(right, bottom - r));
+                if r > 0.0 {
+                    segments.push(Segment::Cubic([right, bottom - k, right - k, bottom, right - r, bottom]));
+                }
+                segments.push(Segment::Line(left + r, bottom));
+                if r > 0.0 {
+                    segments.push(Segment::Cubic([left + k, bottom, left, bottom - k, left, bottom - r]));
+                }
+                segments.push(Segment::Line(left, top + r));
+                if r > 0.0 {
+                    segments.push(Segment::Cubic([left, top + k, left + k, top, left + r, top]));
+                }
+                segments.push(Segment::Close);
+                segments
+            }
+            Outline::Polygon(ref points) => {
+                let mut segments: Vec<Segment> = points.iter().map(|&(x, y)| Segment::Line(x, y)).collect();
+                segments[0] = Segment::Move(points[0].0, points[0].1);
+                segments.push(Segment::Close);
+                segments
+            }
+        }
+    }
+
+    /// Appends the outline as SVG path data.
+    pub fn svg_path(&self, d: &mut String) {
+        let n = pdf::number;
+        for segment in self.segments() {
+            match segment {
+                Segment::Move(x, y) => write!(d, "M{} {}", n(x), n(y)).unwrap(),
+                Segment::Line(x, y) => write!(d, "L{} {}", n(x), n(y)).unwrap(),
+                Segment::Cubic(p) => write!(d, "C{}", p.map(n).join(" ")).unwrap(),
+                Segment::Close => d.push('Z'),
+            }
+        }
+    }
+
+    /// Appends the outline as PDF path operators.
+    pub fn pdf_path(&self, content: &mut String) {
+        let n = pdf::number;
+        for segment in self.segments() {
+            match segment {
+                Segment::Move(x, y) => writeln!(content, "{} {} m", n(x), n(y)).unwrap(),
+                Segment::Line(x, y) => writeln!(content, "{} {} l", n(x), n(y)).unwrap(),
+                Segment::Cubic(p) => writeln!(content, "{} c", p.map(n).join(" ")).unwrap(),
+                Segment::Close => content.push_str("h\n"),
+            }
+        }
+    }
+}
+
+/// A frame around the symbol and its quiet zone, in pixels.
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    /// Where the symbol and its quiet zone start.
+    pub left: usize,
+    pub top: usize,
+    /// Outlines to fill, in order.
+    pub outlines: Vec<(Outline, Fill)>,
+    /// The text, fitted on one line, with its font size, width, where it
+    /// starts and where its baseline is.
+    pub text: String,
+    pub text_size: f32,
+    pub text_width: f32,
+    pub text_left: f32,
+    pub baseline: f32,
+}
+
+impl Frame {
+    /// Lays out a frame around a symbol and its quiet zone, `width` by
+    /// `height` pixels, whose modules are `module` pixels wide.
+    pub fn new(kind: FrameKind, text: &str, width: usize, height: usize, module: usize) -> Self {
+        let (w, h, m) = (width as f64, height as f64, module as f64);
+        let banner = (w * BANNER_RATIO).round().max(4.0 * m).max(2.0 * MIN_TEXT_SIZE as f64);
+        let border = match kind {
+            FrameKind::Badge => 2.0 * m,
+            FrameKind::Banner | FrameKind::Bubble => m,
+        };
+        let outer = w + 2.0 * border;
+
+        // The text goes in a box left, top, wide and high.
+        let (outlines, text_box, total) = match kind {
+            FrameKind::Banner => (
+                vec![
+                    (Outline::Rect(0.0, 0.0, outer, h + border + banner, 0.0), Fill::Frame),
+                    (Outline::Rect(border, border, w, h, 0.0), Fill::Background),
+                ],
+                (border, h + border, w, banner),
+                h + border + banner,
+            ),
+            FrameKind::Badge => (
+                vec![
+                    (Outline::Rect(0.0, 0.0, outer, h + border + banner, 3.0 * m), Fill::Frame),
+                    (Outline::Rect(border, border, w, h, m), Fill::Background),
+                ],
+                (border, h + border, w, banner),
+                h + border + banner,
+            ),
+            FrameKind::Bubble => {
+                // The bubble hangs a module below the border, and its pointer
+                // reaches up into the gap.
+                let pointer = (banner / 3.0).round();
+                let bubble = h + 2.0 * border + m + pointer;
+                let centre = outer / 2.0;
+                (
+                    vec![
+                        (Outline::Rect(0.0, 0.0, outer, h + 2.0 * border, 2.0 * m), Fill::Frame),
+                        (Outline::Rect(border, border, w, h, m), Fill::Background),
+                        (Outline::Rect(0.0, bubble, outer, banner, banner / 2.0), Fill::Frame),
+                        (
+                            Outline::Polygon(vec![
+                                (centre, bubble - pointer),
+                                (centre + pointer, bubble),
+                                (centre - pointer, bubble),
+                            ]),
+                            Fill::Frame,
+                        ),
+                    ],
+                    (banner / 2.0, bubble, outer - banner, banner),
+                    bubble + banner,
+                )
+            }
+        };
+
+        let (box_left, box_top, box_width, box_height) = text_box;
+        let max_size = box_height as f32 * MAX_TEXT_RATIO;
+        let min_size = (box_height as f32 * MIN_TEXT_RATIO).max(MIN_TEXT_SIZE);
+        let available = (box_width - 2.0 * m).max(1.0) as f32;
+        let (text, text_size) = caption::fit_line(text, available, max_size, min_size);
+        let text_width = caption::text_width(&text, text_size);
+
+        Frame {
+            width: outer as usize,
+            height: total as usize,
+            left: border as usize,
+            top: border as usize,
+            outlines,
+            text_left: (box_left + box_width / 2.0) as f32 - text_width / 2.0,
+            baseline: (box_top + box_height / 2.0) as f32 + caption::baseline_offset(text_size),
+            text,
+            text_size,
+            text_width,
+        }
+    }
+
+    /// Appends the frame as path data, `path` being `Outline::svg_path` or
+    /// `Outline::pdf_path`, to be filled with the even-odd rule so that holes
+    /// show the background.
+    pub fn path(&self, d: &mut String, path: fn(&Outline, &mut String)) {
+        for (outline, _) in &self.outlines {
+            path(outline, d);
+        }
+    }
+
+    /// Calls `plot` with the coverage of every pixel the text touches.
+    pub fn draw_text(&self, plot: impl FnMut(u32, u32, f32)) {
+        caption::draw_line(&self.text, self.text_size, self.text_left, self.baseline, plot);
+    }
+
+    /// Appends the outlines of the glyphs of the text as PDF path operators,
+    /// to be filled with the nonzero winding rule.
+    pub fn text_pdf_path(&self, content: &mut String) {
+        let n = |v: f32| pdf::number(v as f64);
+        let mut end: Option<(f32, f32)> = None;
+        caption::outline_line(&self.text, self.text_size, self.text_left, self.baseline, |points| {
+            let (start, last) = (points[0], points[points.len() - 1]);
+            if end != Some(start) {
+                if end.is_some() {
+                    content.push_str("h\n");
+                }
+                writeln!(content, "{} {} m", n(start.0), n(start.1)).unwrap();
+            }
+            match *points {
+                [_, (x, y)] => writeln!(content, "{} {} l", n(x), n(y)).unwrap(),
+                // PDF has no quadratic curves, but each is a cubic one.
+                [p0, p1, p2] => {
+                    let c1 = (p0.0 + 2.0 / 3.0 * (p1.0 - p0.0), p0.1 + 2.0 / 3.0 * (p1.1 - p0.1));
+                    let c2 = (p2.0 + 2.0 / 3.0 * (p1.0 - p2.0), p2.1 + 2.0 / 3.0 * (p1.1 - p2.1));
+                    writeln!(content, "{} {} {} {} {} {} c", n(c1.0), n(c1.1), n(c2.0), n(c2.1), n(p2.0), n(p2.1))
+                        .unwrap()
+                }
+                [_, p1, p2, p3] => {
+                    writeln!(content, "{} {} {} {} {} {} c", n(p1.0), n(p1.1), n(p2.0), n(p2.1), n(p3.0), n(p3.1))
+                        .unwrap()
+                }
+                _ => {}
+            }
+            end = Some(last);
+        });
+        if end.is_some() {
+            content.push_str("h\n");
+        }
+    }
+}
@@ -0,0 +1,468 @@
+use std::fmt::Write;
+use std::io::Cursor;
+
+use rocket::http::Header;
+use rocket::route::Outcome;
+use rocket::{Data, Request, Response};
+
+use crate::api;
+use crate::batch::{self, Job, Limits};
+use crate::error::Error;
+use crate::options::{self, OptionError, Options, Page};
+use crate::pdf::{self, Document};
+use crate::symbol::Symbol;
+use crate::{encode, render};
+
+/// Path of the label sheet endpoint.
+pub const PATH: &str = "/api/v1/labels";
+
+/// Size of caption text, in points, unless it has to shrink to fit.
+const CAPTION_SIZE: f64 = 8.0;
+
+/// Smallest size captions shrink to before being cut short.
+const MIN_CAPTION_SIZE: f64 = 5.0;
+
+/// Advance width of every Courier glyph, relative to the font size.
+const COURIER_ADVANCE: f64 = 0.6;
+
+/// Space kept clear inside each label, in points, unless `padding` says
+/// otherwise.
+const DEFAULT_PADDING: f64 = mm(1.5);
+
+const fn mm(mm: f64) -> f64 {
+    mm / 25.4 * 72.0
+}
+
+const fn inches(inches: f64) -> f64 {
+    inches * 72.0
+}
+
+/// Geometry of a sheet of labels, in points.
+#[derive(Clone, Copy)]
+struct Sheet {
+    page: Page,
+    columns: usize,
+    rows: usize,
+    label_width: f64,
+    label_height: f64,
+    /// Distance from the left edge of the page to the first column.
+    margin_left: f64,
+    /// Distance from the top edge of the page to the first row.
+    margin_top: f64,
+    /// Distance between the left edges of neighbouring columns.
+    pitch_x: f64,
+    /// Distance between the top edges of neighbouring rows.
+    pitch_y: f64,
+}
+
+/// Label stock that can be picked with `template`.
+const TEMPLATES: [(&str, Sheet); 4] = [
+    (
+        "5160",
+        Sheet {
+            page: Page::Letter,
+            columns: 3,
+            rows: 10,
+            label_width: inches(2.625),
+            label_height: inches(1.0),
+            margin_left: inches(0.1875),
+            margin_top: inches(0.5),
+            pitch_x: inches(2.75),
+            pitch_y: inches(1.0),
+        },
+    ),
+    (
+        "5163",
+        Sheet {
+            page: Page::Letter,
+            columns: 2,
+            rows: 5,
+            label_width: inches(4.0),
+            label_height: inches(2.0),
+            margin_left: inches(0.15625),
+            margin_top: inches(0.5),
+            pitch_x: inches(4.1875),
+            pitch_y: inches(2.0),
+        },
+    ),
+    (
+        "L7160",
+        Sheet {
+            page: Page::A4,
+            columns: 3,
+            rows: 7,
+            label_width: mm(63.5),
+            label_height: mm(38.1),
+            margin_left: mm(7.21),
+            margin_top: mm(15.15),
+            pitch_x: mm(66.04),
+            pitch_y: mm(38.1),
+        },
+    ),
+    (
+        "L7163",
+        Sheet {
+            page: Page::A4,
+            columns: 2,
+            rows: 7,
+            label_width: mm(99.1),
+            label_height: mm(38.1),
+            margin_left: mm(4.65),
+            margin_top: mm(15.15),
+            pitch_x: mm(101.6),
+            pitch_y: mm(38.1),
+        },
+    ),
+];
+
+/// What to print under each code.
+#[derive(Clone, Copy, PartialEq)]
+enum Captions {
+    None,
+    Id,
+    Content,
+}
+
+impl Captions {
+    /// Height taken at the bottom of each label, in points.
+    fn height(self) -> f64 {
+        match self {
+            Captions::None => 0.0,
+            _ => CAPTION_SIZE * 1.25,
+        }
+    }
+}
+
+/// Sheet options, read from the query string before the rendering options.
+/// Named templates can be adjusted field by field, for instance to make up
+/// for a printer's offset.
+struct SheetOptions {
+    template: Option<Sheet>,
+    page: Option<Page>,
+    columns: Option<usize>,
+    rows: Option<usize>,
+    label_width: Option<f64>,
+    label_height: Option<f64>,
+    margin_left: Option<f64>,
+    margin_top: Option<f64>,
+    pitch_x: Option<f64>,
+    pitch_y: Option<f64>,
+    padding: f64,
+    captions: Captions,
+}
+
+impl SheetOptions {
+    fn new() -> Self {
+        SheetOptions {
+            template: None,
+            page: None,
+            columns: None,
+            rows: None,
+            label_width: None,
+            label_height: None,
+            margin_left: None,
+            margin_top: None,
+            pitch_x: None,
+            pitch_y: None,
+            padding: DEFAULT_PADDING,
+            captions: Captions::None,
+        }
+    }
+
+    /// Applies a single `key=value` pair, like `Options::set`.
+    fn set(&mut self, key: &str, value: &str) -> Result<bool, OptionError> {
+        match key {
+            "template" => self.template = Some(parse_template(key, value)?),
+            "page" => self.page = Some(parse_sheet_page(key, value)?),
+            "columns" => self.columns = Some(options::parse_range(key, value, 1, 100)?),
+            "rows" => self.rows = Some(options::parse_range(key, value, 1, 100)?),
+            "label_width" => self.label_width = Some(parse_physical(key, value)?),
+            "label_height" => self.label_height = Some(parse_physical(key, value)?),
+            "margin_left" => self.margin_left = Some(parse_physical(key, value)?),
+            "margin_top" => self.margin_top = Some(parse_physical(key, value)?),
+            "pitch_x" => self.pitch_x = Some(parse_physical(key, value)?),
+            "pitch_y" => self.pitch_y = Some(parse_physical(key, value)?),
+            "padding" => self.padding = parse_physical(key, value)?,
+            "captions" => self.captions = parse_captions(key, value)?,
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /// Combines the template with the fields that override it. Without a
+    /// template, the grid is centred on the page unless margins are given, and
+    /// labels are packed edge to edge unless pitches are given.
+    fn sheet(&self) -> Result<Sheet, Error> {
+        let template = self.template;
+        let label_width = self.label_width.or(template.map(|t| t.label_width));
+        let label_height = self.label_height.or(template.map(|t| t.label_height));
+        let columns = self.columns.or(template.map(|t| t.columns));
+        let rows = self.rows.or(template.map(|t| t.rows));
+        let (Some(label_width), Some(label_height), Some(columns), Some(rows)) =
+            (label_width, label_height, columns, rows)
+        else {
+            let missing = [
+                ("label_width", label_width.is_none()),
+                ("label_height", label_height.is_none()),
+                ("columns", columns.is_none()),
+                ("rows", rows.is_none()),
+            ];
+            let errors = missing
+                .into_iter()
+                .filter(|&(_, missing)| missing)
+                .map(|(field, _)| OptionError::new(field, "required without a template"))
+                .collect();
+            return Err(Error::Options(errors));
+        };
+
+        let page = self.page.or(template.map(|t| t.page)).unwrap_or(Page::Letter);
+        let (page_width, page_height) = page.size().unwrap();
+        let pitch_x = self.pitch_x.or(template.map(|t| t.pitch_x)).unwrap_or(label_width);
+        let pitch_y = self.pitch_y.or(template.map(|t| t.pitch_y)).unwrap_or(label_height);
+        let grid_width = pitch_x * (columns - 1) as f64 + label_width;
+        let grid_height = pitch_y * (rows - 1) as f64 + label_height;
+        let margin_left = self.margin_left.or(template.map(|t| t.margin_left));
+        let margin_top = self.margin_top.or(template.map(|t| t.margin_top));
+        let mut errors = Vec::new();
+        let sheet = Sheet {
+            page,
+            columns,
+            rows,
+            label_width,
+            label_height,
+            margin_left: margin_left.unwrap_or((page_width - grid_width) / 2.0),
+            margin_top: margin_top.unwrap_or((page_height - grid_height) / 2.0),
+            pitch_x,
+            pitch_y,
+        };
+
+        // Allow for rounding in the template measurements.
+        let slack = 0.01;
+        if sheet.pitch_x < label_width - slack {
+            errors.push(OptionError::new("pitch_x", "must be at least `label_width`"));
+        }
+        if sheet.pitch_y < label_height - slack {
+            errors.push(OptionError::new("pitch_y", "must be at least `label_height`"));
+        }
+        if sheet.margin_left < 0.0 || sheet.margin_left + grid_width > page_width + slack {
+            errors.push(OptionError::new("columns", format!("the labels do not fit across the {} page", page.name())));
+        }
+        if sheet.margin_top < 0.0 || sheet.margin_top + grid_height > page_height + slack {
+            errors.push(OptionError::new("rows", format!("the labels do not fit down the {} page", page.name())));
+        }
+        if 2.0 * self.padding >= label_width.min(label_height) {
+            errors.push(OptionError::new("padding", "leaves no room on the label"));
+        } else if 2.0 * self.padding + self.captions.height() >= label_height {
+            errors.push(OptionError::new("padding", "leaves no room on the label for the code and its caption"));
+        }
+
+        match errors.is_empty() {
+            true => Ok(sheet),
+            false => Err(Error::Options(errors)),
+        }
+    }
+}
+
+fn parse_template(key: &str, value: &str) -> Result<Sheet, OptionError> {
+    TEMPLATES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(value))
+        .map(|&(_, sheet)| sheet)
+        .ok_or_else(|| {
+            let names: Vec<_> = TEMPLATES.iter().map(|(name, _)| *name).collect();
+            let (last, rest) = names.split_last().unwrap();
+            OptionError::new(key, format!("expected one of {} or {}", rest.join(", "), last))
+        })
+}
+
+fn parse_sheet_page(key: &str, value: &str) -> Result<Page, OptionError> {
+    match options::parse_page(key, value)? {
+        Page::Fit => Err(OptionError::new(key, "expected A4 or Letter")),
+        page => Ok(page),
+    }
+}
+
+/// Parses a length in `mm` or `in`, and converts it to points.
+fn parse_physical(key: &str, value: &str) -> Result<f64, OptionError> {
+    match options::parse_length(key, value) {
+        Ok(length) if length.is_physical() => Ok(length.points(options::DEFAULT_DPI)),
+        _ => Err(OptionError::new(key, "expected a length in mm or in, such as 25.4mm")),
+    }
+}
+
+fn parse_captions(key: &str, value: &str) -> Result<Captions, OptionError> {
+    match value.to_ascii_lowercase().as_str() {
+        "none" => Ok(Captions::None),
+        "id" => Ok(Captions::Id),
+        "content" => Ok(Captions::Content),
+        _ => Err(OptionError::new(key, "expected one of none, id or content")),
+    }
+}
+
+/// Encodes text as a PDF string for WinAnsiEncoding, which matches Latin-1
+/// for printable characters. Anything else is replaced with `?`.
+fn pdf_string(text: &str) -> String {
+    let mut string = String::from("(");
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => write!(string, "\\{}", c).unwrap(),
+            ' '..='~' => string.push(c),
+            '\u{a0}'..='\u{ff}' => write!(string, "\\{:03o}", c as u32).unwrap(),
+            _ => string.push('?'),
+        }
+    }
+    string.push(')');
+    string
+}
+
+/// Shrinks the caption to fit in `width` points, and cuts it short with an
+/// ellipsis when even the smallest size is too wide.
+fn fit_caption(text: &str, width: f64) -> (String, f64) {
+    let text: String = text.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+    let length = text.chars().count().max(1) as f64;
+    let size = (width / (length * COURIER_ADVANCE)).min(CAPTION_SIZE);
+    if size >= MIN_CAPTION_SIZE {
+        return (text, size);
+    }
+
+    let fits = (width / (MIN_CAPTION_SIZE * COURIER_ADVANCE)) as usize;
+    let text = text.chars().take(fits.saturating_sub(3)).chain("...".chars()).collect();
+    (text, MIN_CAPTION_SIZE)
+}
+
+/// Lays out the codes in order, row by row, over as many pages as needed.
+fn render(sheet: &Sheet, padding: f64, captions: Captions, labels: &[(&Job, Symbol)]) -> Vec<u8> {
+    let mut document = Document::new();
+    let font = document.add("<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>");
+    let resources = format!("/Font << /F1 {} 0 R >>", font);
+    let (page_width, page_height) = sheet.page.size().unwrap();
+    let caption_height = captions.height();
+
+    for page in labels.chunks(sheet.columns * sheet.rows) {
+        let mut content = String::new();
+        for (i, (job, symbol)) in page.iter().enumerate() {
+            let (column, row) = (i % sheet.columns, i / sheet.columns);
+            let left = sheet.margin_left + column as f64 * sheet.pitch_x + padding;
+            let top = page_height - sheet.margin_top - row as f64 * sheet.pitch_y - padding;
+            let width = sheet.label_width - 2.0 * padding;
+            let height = sheet.label_height - 2.0 * padding - caption_height;
+            render::pdf_symbol(&mut content, symbol, &job.options, (left, top), (width, height));
+
+            let text = match captions {
+                Captions::None => continue,
+                Captions::Id => &job.id,
+                Captions::Content => &job.content,
+            };
+            let (text, size) = fit_caption(text, width);
+            let text_width = text.chars().count() as f64 * size * COURIER_ADVANCE;
+            // Courier descends about a fifth of its size below the baseline.
+            let baseline = top - height - caption_height + size * 0.25;
+            writeln!(
+                content,
+                "BT 0 g /F1 {} Tf {} {} Td {} Tj ET",
+                pdf::number(size),
+                pdf::number(left + (width - text_width) / 2.0),
+                pdf::number(baseline),
+                pdf_string(&text)
+            )
+            .unwrap();
+        }
+        document.add_page(page_width, page_height, &resources, &content);
+    }
+
+    document.finish()
+}
+
+/// Reads the query string, then the items, and encodes them. Every invalid
+/// item is reported, since a sheet with gaps would be misprinted.
+async fn generate(req: &'_ Request<'_>, body: Data<'_>, limits: Limits) -> Result<Vec<u8>, Error> {
+    let mut sheet_options = SheetOptions::new();
+    let mut defaults = Options::default();
+    let mut errors = Vec::new();
+    for segment in req.uri().query().into_iter().flat_map(|query| query.raw_segments()) {
+        let (key, value) = segment.split_at_byte(b'=');
+        let (key, value) = (key.url_decode_lossy(), value.url_decode_lossy());
+        let result = match sheet_options.set(&key, &value) {
+            Ok(false) => defaults.set(&key, &value),
+            result => result,
+        };
+        match result {
+            Ok(true) => {}
+            Ok(false) => errors.push(OptionError::new(&key, "unknown option")),
+            Err(e) => errors.push(e),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(Error::Options(errors));
+    }
+    let sheet = sheet_options.sheet()?;
+
+    let items = batch::read_items(req, body, limits).await?;
+    if items.is_empty() {
+        return Err(OptionError::new("body", "expected at least one item").into());
+    }
+    let mut jobs = Vec::with_capacity(items.len());
+    for (index, item) in items.iter().enumerate() {
+        match batch::parse_item(index, item, &defaults) {
+            Ok(job) => match job.options.image_only().or(job.options.frame.map(|_| "frame")) {
+                Some(field) => errors.push(item_error(index, Some(field), "not available on labels".into())),
+                None => jobs.push(job),
+            },
+            Err(item_errors) => {
+                errors.extend(item_errors.into_iter().map(|e| item_error(index, Some(&e.field), e.message)))
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(Error::Options(errors));
+    }
+
+    rocket::tokio::task::spawn_blocking(move || {
+        let symbols = batch::run_all(&jobs, |job| {
+            render::check_contrast(&job.options)?;
+            encode::encode(&job.content, &job.options)
+        });
+
+        let mut labels = Vec::with_capacity(jobs.len());
+        for (job, symbol) in jobs.iter().zip(symbols) {
+            match symbol {
+                Ok(symbol) => labels.push((job, symbol)),
+                Err(Error::Internal(e)) => return Err(Error::Internal(e)),
+                Err(Error::Option(e)) => errors.push(item_error(job.index, Some(&e.field), e.message)),
+                Err(e) => errors.push(item_error(job.index, None, e.to_string())),
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(render(&sheet, sheet_options.padding, sheet_options.captions, &labels)),
+            false => Err(Error::Options(errors)),
+        }
+    })
+    .await
+    .map_err(|e| Error::Internal(Box::new(e)))?
+}
+
+/// An error about one item, with its position in the field name, such as
+/// `items[3].ec`.
+fn item_error(index: usize, field: Option<&str>, message: String) -> OptionError {
+    let field = match field {
+        Some(field) => format!("items[{}].{}", index, field),
+        None => format!("items[{}]", index),
+    };
+    OptionError::new(&field, message)
+}
+
+/// Answers `POST /api/v1/labels` with a PDF of label sheets, one code per
+/// label, fed with the same items as the batch endpoint.
+pub async fn handle<'r>(req: &'r Request<'_>, data: Data<'r>, limits: Limits) -> Outcome<'r> {
+    match generate(req, data, limits).await {
+        Ok(pdf) => Outcome::Success(
+            Response::build()
+                .header(Header::new("Content-Type", "application/pdf"))
+                .sized_body(pdf.len(), Cursor::new(pdf))
+                .finalize(),
+        ),
+        Err(e) => api::make_error(e),
+    }
+}
@@ -0,0 +1,79 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use image::{ImageFormat, ImageReader, Limits, RgbaImage};
+
+use crate::options::OptionError;
+
+/// Largest logo file accepted, uploaded or not.
+pub const MAX_LOGO_BYTES: u64 = 1024 * 1024;
+
+/// Largest side of a logo once decoded, which keeps small files from
+/// expanding into huge images.
+const MAX_LOGO_DIMENSION: u32 = 4096;
+
+/// Formats logos can be given in.
+const FORMATS: [ImageFormat; 5] = [
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::WebP,
+    ImageFormat::Gif,
+    ImageFormat::Bmp,
+];
+
+/// Directory of the logos that `logo` can name, from `QQR_LOGO_DIR`.
+fn asset_dir() -> PathBuf {
+    std::env::var_os("QQR_LOGO_DIR").unwrap_or_else(|| "logos".into()).into()
+}
+
+/// An image to put in the middle of the symbol.
+pub struct Logo {
+    pub image: RgbaImage,
+    /// The file as given, which SVG output embeds as is.
+    pub data: Vec<u8>,
+    pub content_type: &'static str,
+}
+
+impl Logo {
+    /// Decodes an uploaded logo.
+    pub fn decode(key: &str, data: Vec<u8>) -> Result<Arc<Logo>, OptionError> {
+        let error = || OptionError::new(key, "expected a PNG, JPEG, WebP, GIF or BMP image");
+        if data.len() as u64 > MAX_LOGO_BYTES {
+            return Err(OptionError::new(key, format!("expected at most {} bytes", MAX_LOGO_BYTES)));
+        }
+
+        let mut reader = ImageReader::new(Cursor::new(&data)).with_guessed_format().map_err(|_| error())?;
+        let format = reader.format().filter(|format| FORMATS.contains(format)).ok_or_else(error)?;
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(MAX_LOGO_DIMENSION);
+        limits.max_image_height = Some(MAX_LOGO_DIMENSION);
+        reader.limits(limits);
+        let image = reader.decode().map_err(|_| error())?.into_rgba8();
+
+        Ok(Arc::new(Logo {
+            image,
+            data,
+            content_type: format.to_mime_type(),
+        }))
+    }
+
+    /// Loads one of the logos installed on the server, by file name without
+    /// its extension.
+    pub fn load_asset(key: &str, name: &str) -> Result<Arc<Logo>, OptionError> {
+        let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if name.is_empty() || name.len() > 64 || !valid {
+            return Err(OptionError::new(key, "expected the name of a logo, in letters, digits, `-` and `_`"));
+        }
+
+        let dir = asset_dir();
+        for extension in FORMATS.iter().flat_map(|format| format.extensions_str()) {
+            let path = dir.join(format!("{}.{}", name, extension));
+            if let Ok(data) = std::fs::read(&path) {
+                return Logo::decode(key, data);
+            }
+        }
+
+        Err(OptionError::new(key, format!("there is no logo named {}", name)))
+    }
+}
@@ -1,71 +1,160 @@
+mod api;
+mod batch;
+mod caption;
+mod color;
+mod embed;
+mod encode;
+mod error;
+mod format;
+mod frame;
+mod labels;
+mod logo;
+mod options;
+mod pdf;
+mod render;
+mod rmqr;
+mod shape;
+mod symbol;
+
 use std::io::Cursor;
 use std::net::Ipv4Addr;
 
-use image::{ImageFormat, Luma};
-use qrcode::render::svg;
-use qrcode::QrCode;
-use rocket::data::ToByteUnit;
-use rocket::form::Form;
+use image::ImageFormat;
+use rocket::data::{FromData, ToByteUnit};
+use rocket::form::{DataField, Form, FromForm, ValueField};
 use rocket::http::{Header, Method, RawStr, Status};
 use rocket::route::{Handler, Outcome};
-use rocket::{Config, Data, FromForm, Request, Response, Route};
-
-enum OutputFormat {
-    PNG,
-    SVG,
-}
+use rocket::{Config, Data, Request, Response, Route};
 
-fn make_qrcode(content: &str, format: &OutputFormat) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let code = QrCode::new(content)?;
+use error::Error;
+use format::OutputFormat;
+use logo::Logo;
+use options::{Embed, OptionError, Options};
+use render::TextStyle;
 
-    let mut bytes: Vec<u8> = Vec::new();
+fn make_qrcode(content: &str, format: OutputFormat, options: &Options) -> Result<Vec<u8>, Error> {
+    render::check_contrast(options)?;
+    if let Some(field) = options.image_only().filter(|_| !format.is_web_image()) {
+        return Err(OptionError::new(field, format!("not available in {} output", format.name())).into());
+    }
+    if options.frame.is_some() && !format.is_web_image() && format != OutputFormat::PDF {
+        return Err(OptionError::new("frame", format!("not available in {} output", format.name())).into());
+    }
+    let symbol = encode::encode(content, options)?;
+    if options.logo.is_some() {
+        render::verify(&symbol, content, options)?;
+    }
 
     match format {
-        OutputFormat::PNG => {
-            let image = code.render::<Luma<u8>>().min_dimensions(1000, 1000).build();
-            image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
-        }
-        OutputFormat::SVG => {
-            bytes = code.render()
-                .dark_color(svg::Color("#000000"))
-                .light_color(svg::Color("#ffffff"))
-                .min_dimensions(1000, 1000)
-                .build().into();
-        }
+        OutputFormat::PNG => render::png(&symbol, options),
+        OutputFormat::SVG => Ok(render::svg(&symbol, options)?.into()),
+        OutputFormat::JPEG => render::raster(&symbol, options, ImageFormat::Jpeg),
+        OutputFormat::WebP => render::raster(&symbol, options, ImageFormat::WebP),
+        OutputFormat::GIF => render::raster(&symbol, options, ImageFormat::Gif),
+        OutputFormat::BMP => render::raster(&symbol, options, ImageFormat::Bmp),
+        OutputFormat::PDF => render::pdf(&symbol, options),
+        OutputFormat::EPS => render::eps(&symbol, options),
+        OutputFormat::Text => Ok(render::text(&symbol, options, TextStyle::Unicode).into()),
+        OutputFormat::ASCII => Ok(render::text(&symbol, options, TextStyle::ASCII).into()),
+        OutputFormat::ANSI => Ok(render::text(&symbol, options, TextStyle::ANSI).into()),
+        OutputFormat::Sixel => Ok(render::sixel(&symbol, options)?.into()),
+        OutputFormat::Kitty => Ok(render::kitty(&symbol, options)?.into()),
+        OutputFormat::JSON => Ok(render::json(&symbol, options).into()),
     }
-
-    Ok(bytes)
 }
 
-fn make_and_return_qrcode<'a>(content: &str, format: &OutputFormat) -> Outcome<'a> {
-    let code = make_qrcode(content, format);
+/// Wraps the output as asked by `embed`, and returns it with its content type.
+fn embed_qrcode(
+    content: &str,
+    format: OutputFormat,
+    options: &Options,
+    code: Vec<u8>,
+) -> Result<(&'static str, Vec<u8>), Error> {
+    let embeddable = match options.embed {
+        Embed::None => true,
+        Embed::Data => format.is_file(),
+        Embed::Img => format.is_web_image(),
+    };
+    if !embeddable {
+        return Err(OptionError::new("embed", format!("cannot embed {} output", format.name())).into());
+    }
 
-    let code = match code {
-        Ok(code) => code,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            return Outcome::Error(Status::InternalServerError);
+    match options.embed {
+        Embed::None => Ok((format.content_type(), code)),
+        Embed::Data => Ok(("application/json", embed::json(format, &code).into())),
+        Embed::Img => {
+            let alt = options.alt.clone().unwrap_or_else(|| format!("QR code: {}", content));
+            Ok(("text/html; charset=utf-8", embed::img(format, &code, &alt).into()))
         }
+    }
+}
+
+fn make_and_return_qrcode<'a>(req: &'_ Request<'_>, content: &str, options: &Options) -> Outcome<'a> {
+    // The `Accept` header describes the wrapper of embeds, not the image.
+    // Negotiation also looks at the `User-Agent`, so caches must too.
+    let (format, vary) = match (options.format, options.embed) {
+        (Some(format), _) => (format, "Accept"),
+        (None, Embed::Data | Embed::Img) => (OutputFormat::PNG, "Accept"),
+        (None, Embed::None) => match get_format_from_accept(req) {
+            Ok(format) => (format, "Accept, User-Agent"),
+            Err(e) => return make_error(e),
+        },
     };
 
-    let content_type = match format {
-        OutputFormat::PNG => "image/png",
-        OutputFormat::SVG => "image/svg+xml",
+    let code = make_qrcode(content, format, options);
+    let (content_type, code) = match code.and_then(|code| embed_qrcode(content, format, options, code)) {
+        Ok(code) => code,
+        Err(e) => return make_error(e),
     };
 
+    let contrast = render::contrast_ratio(options);
+
     Outcome::Success(
         Response::build()
             .header(Header::new("Content-Type", content_type))
+            .header(Header::new("Vary", vary))
+            .header(Header::new("X-Contrast-Ratio", format!("{:.2}", contrast)))
             .sized_body(code.len(), Cursor::new(code))
             .finalize(),
     )
 }
 
-fn get_format_from_accept(req: &'_ Request<'_>) -> OutputFormat {
-    match req.headers().get("Accept").find(|&x| x == "image/svg+xml") {
-        Some(_) => OutputFormat::SVG,
-        None => OutputFormat::PNG,
+fn make_error<'a>(error: Error) -> Outcome<'a> {
+    let message = match error {
+        Error::Status(status) => return Outcome::Error(status),
+        Error::Internal(e) => {
+            eprintln!("Error: {}", e);
+            return Outcome::Error(Status::InternalServerError);
+        }
+        ref e => e.to_string(),
+    };
+
+    Outcome::Success(
+        Response::build()
+            .status(error.status())
+            .header(Header::new("Content-Type", "text/plain; charset=utf-8"))
+            .sized_body(message.len(), Cursor::new(message))
+            .finalize(),
+    )
+}
+
+/// Command-line HTTP clients, which get text unless they ask for something
+/// more specific than `*/*`.
+const TEXT_CLIENTS: [&str; 3] = ["curl/", "Wget/", "HTTPie/"];
+
+fn get_format_from_accept(req: &'_ Request<'_>) -> Result<OutputFormat, Error> {
+    let user_agent = req.headers().get_one("User-Agent").unwrap_or_default();
+    if TEXT_CLIENTS.iter().any(|client| user_agent.starts_with(client))
+        && req.headers().get("Accept").all(|accept| accept.trim() == "*/*")
+    {
+        return Ok(OutputFormat::Text);
     }
+
+    format::negotiate(req.headers().get("Accept")).ok_or_else(|| {
+        let mut types: Vec<_> = OutputFormat::ALL.iter().map(|format| format.content_type()).collect();
+        types.dedup();
+        Error::NotAcceptable(format!("none of the accepted types are available; try one of {}", types.join(", ")))
+    })
 }
 
 #[derive(FromForm)]
@@ -73,45 +162,209 @@ struct Body {
     pub input: String,
 }
 
-async fn parse_post(req: &'_ Request<'_>, body: Data<'_>) -> Result<String, Status> {
+/// A `multipart/form-data` body: the same fields as a form, and the logo as a
+/// file.
+#[derive(Default)]
+struct Upload {
+    fields: Vec<(String, String)>,
+    logo: Option<Vec<u8>>,
+    /// Name of the first file that is not `logo`, or of a logo that is too large.
+    rejected: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromForm<'r> for Upload {
+    type Context = Upload;
+
+    fn init(_: rocket::form::Options) -> Self::Context {
+        <Upload as Default>::default()
+    }
+
+    fn push_value(upload: &mut Self::Context, field: ValueField<'r>) {
+        upload.fields.push((field.name.source().to_string(), field.value.to_string()));
+    }
+
+    async fn push_data(upload: &mut Self::Context, field: DataField<'r, '_>) {
+        let name = field.name.source().to_string();
+        if name != "logo" || upload.logo.is_some() {
+            upload.rejected.get_or_insert(name);
+            return;
+        }
+
+        match field.data.open(logo::MAX_LOGO_BYTES.bytes()).into_bytes().await {
+            Ok(data) if data.is_complete() => upload.logo = Some(data.into_inner()),
+            _ => upload.rejected = Some(name),
+        }
+    }
+
+    fn finalize(upload: Self::Context) -> rocket::form::Result<'r, Self> {
+        Ok(upload)
+    }
+}
+
+/// Prefix of options in the query string of GET requests, which keeps them
+/// apart from the parameters of URLs being encoded.
+const OPTION_PREFIX: &str = "qqr.";
+
+/// Reads options from the query string, named with `OPTION_PREFIX`, or also
+/// without it when `bare` is set. Other parameters are returned so that GET
+/// requests can keep them as part of the content.
+fn parse_query<'a>(
+    req: &'a Request<'_>,
+    options: &mut Options,
+    bare: bool,
+) -> Result<Option<Vec<&'a str>>, OptionError> {
+    let query = match req.uri().query() {
+        Some(query) => query,
+        None => return Ok(None),
+    };
+
+    let mut rest = Vec::new();
+    for segment in query.raw_segments() {
+        let (key, value) = segment.split_at_byte(b'=');
+        let key = key.url_decode_lossy();
+        let known = match key.strip_prefix(OPTION_PREFIX) {
+            Some(name) if !options.set(name, &value.url_decode_lossy())? => {
+                return Err(OptionError::new(name, "unknown option"));
+            }
+            Some(_) => true,
+            None => bare && options.set(&key, &value.url_decode_lossy())?,
+        };
+        if !known && !segment.is_empty() {
+            rest.push(segment.as_str());
+        }
+    }
+
+    Ok(Some(rest))
+}
+
+fn parse_get(req: &'_ Request<'_>) -> Result<(String, Options), Error> {
+    let mut options = Options::default();
+    let path = req.uri().path();
+    let path = path.as_str().strip_prefix("/").unwrap_or(path.as_str());
+
+    // The extension picks the format unless `format` is given, and is never
+    // part of the content.
+    let (path, extension) = match OutputFormat::strip_extension(path) {
+        Some((path, format)) => (path, Some(format)),
+        None => (path, None),
+    };
+    let mut content = path.to_string();
+
+    // Keep non-option parameters so that URLs with their own query string are
+    // encoded as-is.
+    if let Some(rest) = parse_query(req, &mut options, false)? {
+        if !rest.is_empty() || req.uri().query().is_some_and(|q| q.is_empty()) {
+            content.push('?');
+            content.push_str(&rest.join("&"));
+        }
+    }
+
+    options.format = options.format.or(extension);
+    options.validate()?;
+    Ok((content, options))
+}
+
+async fn parse_post<'r>(req: &'r Request<'_>, body: Data<'r>) -> Result<(String, Options), Error> {
+    let mut options = Options::default();
+    parse_query(req, &mut options, true)?;
+
+    if req.content_type().is_some_and(|content_type| content_type.is_form_data()) {
+        return parse_upload(req, body, options).await;
+    }
+
     let content = match body.open(2.megabytes()).into_string().await {
         Ok(content) => content.into_inner(),
-        Err(_) => return Err(Status::PayloadTooLarge),
+        Err(_) => return Err(Error::Status(Status::PayloadTooLarge)),
     };
 
     match req.content_type() {
         Some(content_type) => {
             // Why can't I match content_type {}?
             if content_type.is_form() {
-                match Form::<Body>::parse(&content) {
-                    Ok(form) => {
-                        Ok(RawStr::percent_decode_lossy(RawStr::new(form.input.as_str())).into())
-                    }
-                    Err(_) => Err(Status::BadRequest),
+                let form = match Form::<Body>::parse(&content) {
+                    Ok(form) => form,
+                    Err(_) => return Err(Error::Status(Status::BadRequest)),
+                };
+
+                for field in Form::values(&content) {
+                    let key = RawStr::new(field.name.source().as_str()).url_decode_lossy();
+                    let value = RawStr::new(field.value).url_decode_lossy();
+                    options.set(&key, &value)?;
                 }
+
+                options.validate()?;
+                Ok((RawStr::percent_decode_lossy(RawStr::new(form.input.as_str())).into(), options))
             } else if content_type.is_plain() {
-                Ok(content)
+                options.validate()?;
+                Ok((content, options))
             } else {
-                Err(Status::UnsupportedMediaType)
+                Err(Error::Status(Status::UnsupportedMediaType))
             }
         }
-        None => Err(Status::BadRequest),
+        None => Err(Error::Status(Status::BadRequest)),
+    }
+}
+
+/// Reads a `multipart/form-data` body, which is how logos are uploaded.
+async fn parse_upload<'r>(
+    req: &'r Request<'_>,
+    body: Data<'r>,
+    mut options: Options,
+) -> Result<(String, Options), Error> {
+    let upload = match Form::<Upload>::from_data(req, body).await {
+        rocket::outcome::Outcome::Success(upload) => upload.into_inner(),
+        _ => return Err(Error::Status(Status::BadRequest)),
+    };
+    if let Some(name) = upload.rejected {
+        let message = match name.as_str() {
+            "logo" => format!("expected a single file of at most {} bytes", logo::MAX_LOGO_BYTES),
+            _ => "unexpected file; only `logo` can be uploaded".into(),
+        };
+        return Err(OptionError::new(&name, message).into());
+    }
+
+    let mut content = None;
+    for (key, value) in upload.fields {
+        match key.as_str() {
+            "input" => content = Some(value),
+            _ => {
+                options.set(&key, &value)?;
+            }
+        }
+    }
+    if let Some(data) = upload.logo {
+        options.logo = Some(Logo::decode("logo", data)?);
+    }
+
+    options.validate()?;
+    match content {
+        Some(content) => Ok((content, options)),
+        None => Err(OptionError::new("input", "expected the text to encode").into()),
     }
 }
 
 #[derive(Clone)]
-struct Server;
+struct Server {
+    batch: batch::Limits,
+}
 
 impl Server {
     fn index() -> &'static str {
-        r#"<!DOCTYPE html><html><head><title>qqr</title></head><body><form style="display:flex;flex-direction:column;flex;align-items:center;justify-content:center;width:100vw;height:100vh;row-gap:0.25em;" action="" method="post"><textarea name="input" style="width:50vw"></textarea><input type="submit" style="width: 25vw"/></form></body></html>"#
+        r#"<!DOCTYPE html><html><head><title>qqr</title></head><body><form style="display:flex;flex-direction:column;flex;align-items:center;justify-content:center;width:100vw;height:100vh;row-gap:0.25em;" action="" method="post"><textarea name="input" style="width:50vw"></textarea><select name="ec" style="width:25vw"><option value="L">Low (7%)</option><option value="M" selected>Medium (15%)</option><option value="Q">Quartile (25%)</option><option value="H">High (30%)</option></select><input type="submit" style="width: 25vw"/><p style="width:50vw">Or <code>GET /&lt;text&gt;</code>, with options prefixed by <code>qqr.</code> in the query string, as in <code>/https://example.com/?page=2&amp;qqr.ec=H</code>. Other parameters are part of the text. A <code>.png</code>, <code>.svg</code>, <code>.jpeg</code>, <code>.webp</code>, <code>.gif</code>, <code>.bmp</code>, <code>.pdf</code> or <code>.eps</code> extension picks the format and is not encoded; repeat it, as in <code>/logo.png.png</code>, to encode text that really ends in one.</p></form></body></html>"#
     }
 }
 
 #[rocket::async_trait]
 impl Handler for Server {
     async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
-        if req.uri().path() == "/" {
+        if req.uri().path() == api::PATH && req.method() == Method::Post {
+            api::handle(req, data).await
+        } else if req.uri().path() == batch::PATH && req.method() == Method::Post {
+            batch::handle(req, data, self.batch).await
+        } else if req.uri().path() == labels::PATH && req.method() == Method::Post {
+            labels::handle(req, data, self.batch).await
+        } else if req.uri().path() == "/" {
             match req.method() {
                 Method::Get => {
                     let html = Server::index();
@@ -123,29 +376,31 @@ impl Handler for Server {
                     )
                 }
                 Method::Post => match parse_post(req, data).await {
-                    Ok(content) => make_and_return_qrcode(&content, &get_format_from_accept(req)),
-                    Err(_) => Outcome::Error(Status::PayloadTooLarge),
+                    Ok((content, options)) => make_and_return_qrcode(req, &content, &options),
+                    Err(e) => make_error(e),
                 },
                 _ => Outcome::Error(Status::MethodNotAllowed),
             }
         } else {
             match req.method() {
-                Method::Get => {
-                    let uri = req.uri().to_string();
-                    let uri = uri.strip_prefix("/").unwrap_or(&uri);
-                    make_and_return_qrcode(uri, &get_format_from_accept(req))
-                }
+                Method::Get => match parse_get(req) {
+                    Ok((content, options)) => make_and_return_qrcode(req, &content, &options),
+                    Err(e) => make_error(e),
+                },
                 _ => Outcome::Error(Status::MethodNotAllowed),
             }
         }
     }
 }
 
-impl Into<Vec<Route>> for Server {
-    fn into(self) -> Vec<Route> {
+impl From<Server> for Vec<Route> {
+    fn from(server: Server) -> Self {
         vec![
-            Route::new(Method::Get, "/<path..>", self.clone()),
-            Route::new(Method::Post, "/", self.clone()),
+            Route::new(Method::Get, "/<path..>", server.clone()),
+            Route::new(Method::Post, "/", server.clone()),
+            Route::new(Method::Post, api::PATH, server.clone()),
+            Route::new(Method::Post, batch::PATH, server.clone()),
+            Route::new(Method::Post, labels::PATH, server.clone()),
         ]
     }
 }
@@ -156,5 +411,8 @@ fn rocket() -> _ {
         address: Ipv4Addr::new(0, 0, 0, 0).into(),
         ..Config::debug_default()
     };
-    rocket::custom(config).mount("/", Server {})
+    let server = Server {
+        batch: batch::Limits::from_env(),
+    };
+    rocket::custom(config).mount("/", server)
 }
@@ -0,0 +1,744 @@
+use std::fmt;
+use std::sync::Arc;
+
+use qrcode::EcLevel;
+
+use crate::color::Color;
+use crate::format::OutputFormat;
+use crate::logo::Logo;
+use crate::render::MAX_DIMENSION;
+use crate::symbol::SymbolKind;
+
+/// Rendering options shared by every route. They are read from the query
+/// string on GET and plain-text POST requests, and from extra form fields on
+/// form POST requests.
+#[derive(Clone)]
+pub struct Options {
+    /// Output format, overriding the path extension and the `Accept` header.
+    pub format: Option<OutputFormat>,
+    pub symbol: SymbolKind,
+    pub ec: EcLevel,
+    /// Exact version to encode with. Versions are numbered from 1 for every
+    /// symbol kind: M1 to M4 are 1 to 4, and R7x43 to R17x139 are 1 to 32.
+    pub version: Option<i16>,
+    /// Smallest version to encode with.
+    pub min_version: Option<i16>,
+    /// Largest version to encode with.
+    pub max_version: Option<i16>,
+    /// Color of the dark modules.
+    pub fg: Color,
+    /// Color of the light modules and the quiet zone.
+    pub bg: Color,
+    /// Render even when the colors fail the contrast checks.
+    pub force: bool,
+    /// Pixels per module.
+    pub scale: Option<usize>,
+    /// Target size of the longest side. Rounded down to a whole number of
+    /// pixels per module.
+    pub size: Option<Length>,
+    /// Upper bound on the longest side.
+    pub max_size: Option<Length>,
+    /// Resolution used to convert physical lengths to pixels, and recorded in
+    /// the output so that it prints at that size.
+    pub dpi: Option<u32>,
+    /// Width of the quiet zone, in modules. Defaults to what the symbol's
+    /// specification requires.
+    pub margin: Option<usize>,
+    /// Page size of document formats.
+    pub page: Page,
+    /// Swap dark and light in text renderings, for dark terminals.
+    pub invert: bool,
+    /// Width and height of a terminal cell in pixels, which terminal graphics
+    /// are sized to.
+    pub cell: (usize, usize),
+    /// Bit-pack the module matrix in JSON output.
+    pub packed: bool,
+    /// Wrap the output for inline use.
+    pub embed: Embed,
+    /// Alternative text of `<img>` embeds. Defaults to describing the content.
+    pub alt: Option<String>,
+    /// Text printed with the symbol, in raster and SVG output.
+    pub caption: Option<String>,
+    pub caption_position: CaptionPosition,
+    /// Image put in the middle of the symbol, which raises `ec` to H.
+    pub logo: Option<Arc<Logo>>,
+    /// Side of the area cleared for the logo, relative to the symbol's.
+    pub logo_size: f64,
+    /// Shape of the dark modules, in raster and SVG output.
+    pub style: ModuleStyle,
+    /// Draw the finder patterns in `style` too, rather than square.
+    pub style_finders: bool,
+    /// Shape of the outer ring of the finder patterns. When none of the `eye_`
+    /// options are set, finder patterns are drawn module by module.
+    pub eye_frame: Option<EyeShape>,
+    /// Shape of the square in the middle of the finder patterns.
+    pub eye_pupil: Option<EyeShape>,
+    /// Colors of the ring and of the middle of the finder patterns. Default
+    /// to `fg`.
+    pub eye_frame_color: Option<Color>,
+    pub eye_pupil_color: Option<Color>,
+    /// Paint the foreground with a gradient through `gradient_stops` rather
+    /// than with `fg`.
+    pub gradient: Option<GradientKind>,
+    /// Direction of linear gradients in degrees, clockwise from pointing up,
+    /// as in CSS.
+    pub gradient_angle: f64,
+    /// Colors of the gradient, at positions from 0 to 1.
+    pub gradient_stops: Vec<(f64, Color)>,
+    /// Decoration around the symbol, with a call to action.
+    pub frame: Option<FrameKind>,
+    pub frame_text: String,
+    /// Color of the frame. Defaults to `fg`.
+    pub frame_color: Option<Color>,
+    /// Color of the text on the frame. Defaults to `bg`, made opaque.
+    pub frame_text_color: Option<Color>,
+    /// Limit on either side of the rendered image, in pixels. Not an option:
+    /// batches lower it to bound the size of the archive.
+    pub max_dimension: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            format: None,
+            symbol: SymbolKind::QR,
+            ec: EcLevel::M,
+            version: None,
+            min_version: None,
+            max_version: None,
+            fg: Color::BLACK,
+            bg: Color::WHITE,
+            force: false,
+            scale: None,
+            size: None,
+            max_size: None,
+            dpi: None,
+            margin: None,
+            page: Page::Fit,
+            invert: false,
+            cell: (10, 20),
+            packed: false,
+            embed: Embed::None,
+            alt: None,
+            caption: None,
+            caption_position: CaptionPosition::Below,
+            logo: None,
+            logo_size: 0.2,
+            style: ModuleStyle::Square,
+            style_finders: false,
+            eye_frame: None,
+            eye_pupil: None,
+            eye_frame_color: None,
+            eye_pupil_color: None,
+            gradient: None,
+            gradient_angle: 180.0,
+            gradient_stops: Vec::new(),
+            frame: None,
+            frame_text: "SCAN ME".into(),
+            frame_color: None,
+            frame_text_color: None,
+            max_dimension: MAX_DIMENSION,
+        }
+    }
+}
+
+/// Most color stops a gradient may have.
+const MAX_GRADIENT_STOPS: usize = 8;
+
+/// Longest frame text accepted, in characters.
+const MAX_FRAME_TEXT_LENGTH: usize = 40;
+
+/// Longest caption accepted, in characters.
+const MAX_CAPTION_LENGTH: usize = 200;
+
+/// Largest physical length accepted, whatever the resolution.
+const MAX_PHYSICAL_MM: f64 = 1016.0;
+
+/// Resolution assumed when a physical length is given without `dpi`, and
+/// when laying out vector formats.
+pub const DEFAULT_DPI: u32 = 300;
+
+/// A length in pixels, or in physical units that depend on the resolution.
+#[derive(Clone, Copy)]
+pub enum Length {
+    Pixels(usize),
+    Millimetres(f64),
+    Inches(f64),
+}
+
+impl Length {
+    pub fn is_physical(self) -> bool {
+        !matches!(self, Length::Pixels(_))
+    }
+
+    /// Converts to PostScript points, of which there are 72 per inch.
+    pub fn points(self, dpi: u32) -> f64 {
+        match self {
+            Length::Pixels(pixels) => pixels as f64 * 72.0 / dpi as f64,
+            Length::Millimetres(mm) => mm / 25.4 * 72.0,
+            Length::Inches(inches) => inches * 72.0,
+        }
+    }
+
+    /// Converts to pixels at `dpi`, rounding down.
+    pub fn pixels(self, dpi: u32) -> usize {
+        match self {
+            Length::Pixels(pixels) => pixels,
+            Length::Millimetres(mm) => (mm / 25.4 * dpi as f64) as usize,
+            Length::Inches(inches) => (inches * dpi as f64) as usize,
+        }
+    }
+}
+
+/// Page size of document formats.
+#[derive(Clone, Copy)]
+pub enum Page {
+    /// As large as the symbol and its quiet zone.
+    Fit,
+    A4,
+    Letter,
+}
+
+impl Page {
+    /// Name, as accepted by `page`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Page::Fit => "fit",
+            Page::A4 => "A4",
+            Page::Letter => "Letter",
+        }
+    }
+
+    /// Width and height in points, unless the page fits the symbol.
+    pub fn size(self) -> Option<(f64, f64)> {
+        match self {
+            Page::Fit => None,
+            Page::A4 => Some((210.0 / 25.4 * 72.0, 297.0 / 25.4 * 72.0)),
+            Page::Letter => Some((612.0, 792.0)),
+        }
+    }
+}
+
+/// Ways of wrapping the output for pages that cannot make a second request.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Embed {
+    /// The output itself.
+    None,
+    /// A JSON object with a `data:` URI.
+    Data,
+    /// An HTML `<img>` tag with a `data:` URI.
+    Img,
+}
+
+/// Where the caption goes.
+#[derive(Clone, Copy, PartialEq)]
+pub enum CaptionPosition {
+    Below,
+    Above,
+}
+
+/// Shape of the dark modules.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ModuleStyle {
+    Square,
+    /// Dots as wide as a module.
+    Circle,
+    /// Squares with rounded corners.
+    Rounded,
+    /// Runs of adjacent modules merged into one shape, rounded only where
+    /// the outline turns outwards.
+    Liquid,
+    /// Squares turned by 45°, with their corners on the middle of each side.
+    Diamond,
+}
+
+impl ModuleStyle {
+    pub const ALL: [ModuleStyle; 5] = [
+        ModuleStyle::Square,
+        ModuleStyle::Circle,
+        ModuleStyle::Rounded,
+        ModuleStyle::Liquid,
+        ModuleStyle::Diamond,
+    ];
+
+    /// Name, as accepted by `style`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ModuleStyle::Square => "square",
+            ModuleStyle::Circle => "circle",
+            ModuleStyle::Rounded => "rounded",
+            ModuleStyle::Liquid => "liquid",
+            ModuleStyle::Diamond => "diamond",
+        }
+    }
+}
+
+/// Shape of the parts of a finder pattern.
+#[derive(Clone, Copy, PartialEq)]
+pub enum EyeShape {
+    Square,
+    Rounded,
+    Circle,
+    /// Only for the pupil, since a diamond ring would hide the pattern.
+    Diamond,
+}
+
+/// Shapes of gradient.
+#[derive(Clone, Copy, PartialEq)]
+pub enum GradientKind {
+    /// Along a line through the middle of the symbol, at `gradient_angle`.
+    Linear,
+    /// Outwards from the middle of the symbol to its corners.
+    Radial,
+}
+
+/// Frames around the symbol.
+#[derive(Clone, Copy, PartialEq)]
+pub enum FrameKind {
+    /// A border with the text on a banner below the symbol.
+    Banner,
+    /// A card with rounded corners and the text below the symbol.
+    Badge,
+    /// A rounded border, and the text in a speech bubble pointing at it.
+    Bubble,
+}
+
+#[derive(Debug)]
+pub struct OptionError {
+    pub field: String,
+    pub message: String,
+}
+
+impl OptionError {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        OptionError {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid value for `{}`: {}", self.field, self.message)
+    }
+}
+
+impl std::error::Error for OptionError {}
+
+impl Options {
+    /// Applies a single `key=value` pair. Returns `Ok(false)` when `key` is not
+    /// an option name, so that callers can leave it in the encoded content.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<bool, OptionError> {
+        match key {
+            "format" => self.format = Some(parse_format(key, value)?),
+            "symbol" => self.symbol = parse_symbol(key, value)?,
+            "ec" => self.ec = parse_ec(key, value)?,
+            "version" => self.version = Some(parse_range(key, value, 1, 40)?),
+            "min_version" => self.min_version = Some(parse_range(key, value, 1, 40)?),
+            "max_version" => self.max_version = Some(parse_range(key, value, 1, 40)?),
+            "fg" => self.fg = parse_color(key, value)?,
+            "bg" => self.bg = parse_color(key, value)?,
+            "force" => self.force = parse_bool(key, value)?,
+            "scale" => self.scale = Some(parse_range(key, value, 1, MAX_DIMENSION)?),
+            "size" => self.size = Some(parse_length(key, value)?),
+            "max_size" => self.max_size = Some(parse_length(key, value)?),
+            "dpi" => self.dpi = Some(parse_range(key, value, 1, 2400)?),
+            "margin" => self.margin = Some(parse_range(key, value, 0, 100)?),
+            "page" => self.page = parse_page(key, value)?,
+            "invert" => self.invert = parse_bool(key, value)?,
+            "cell" => self.cell = parse_cell(key, value)?,
+            "packed" => self.packed = parse_bool(key, value)?,
+            "embed" => self.embed = parse_embed(key, value)?,
+            "alt" => self.alt = Some(value.to_string()),
+            "caption" => self.caption = Some(parse_text(key, value, MAX_CAPTION_LENGTH)?),
+            "caption_position" => self.caption_position = parse_caption_position(key, value)?,
+            "logo" => self.logo = Some(Logo::load_asset(key, value)?),
+            "logo_size" => self.logo_size = parse_range(key, value, 0.05, 0.3)?,
+            "style" => self.style = parse_style(key, value)?,
+            "style_finders" => self.style_finders = parse_bool(key, value)?,
+            "eye_frame" => self.eye_frame = Some(parse_eye_frame(key, value)?),
+            "eye_pupil" => self.eye_pupil = Some(parse_eye_pupil(key, value)?),
+            "eye_frame_color" => self.eye_frame_color = Some(parse_color(key, value)?),
+            "eye_pupil_color" => self.eye_pupil_color = Some(parse_color(key, value)?),
+            "gradient" => self.gradient = Some(parse_gradient(key, value)?),
+            "gradient_angle" => self.gradient_angle = parse_range(key, value, 0.0, 360.0)?,
+            "gradient_stops" => self.gradient_stops = parse_stops(key, value)?,
+            "frame" => self.frame = parse_frame(key, value)?,
+            "frame_text" => self.frame_text = parse_text(key, value, MAX_FRAME_TEXT_LENGTH)?,
+            "frame_color" => self.frame_color = Some(parse_color(key, value)?),
+            "frame_text_color" => self.frame_text_color = Some(parse_color(key, value)?),
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /// Resolution of the output, if it has a physical size: either `dpi`, or a
+    /// default when a length was given in physical units.
+    pub fn resolution(&self) -> Option<u32> {
+        let physical = [self.size, self.max_size].into_iter().flatten().any(Length::is_physical);
+        match physical {
+            true => Some(self.dpi.unwrap_or(DEFAULT_DPI)),
+            false => self.dpi,
+        }
+    }
+
+    /// Whether the finder patterns are drawn as eyes rather than modules.
+    pub fn has_eyes(&self) -> bool {
+        self.eye_frame.is_some()
+            || self.eye_pupil.is_some()
+            || self.eye_frame_color.is_some()
+            || self.eye_pupil_color.is_some()
+    }
+
+    /// Name of the first option set that only raster and SVG output can draw.
+    pub fn image_only(&self) -> Option<&'static str> {
+        [
+            ("caption", self.caption.is_some()),
+            ("logo", self.logo.is_some()),
+            ("style", self.style != ModuleStyle::Square),
+            ("eye_frame", self.eye_frame.is_some()),
+            ("eye_pupil", self.eye_pupil.is_some()),
+            ("eye_frame_color", self.eye_frame_color.is_some()),
+            ("eye_pupil_color", self.eye_pupil_color.is_some()),
+            ("gradient", self.gradient.is_some()),
+        ]
+        .into_iter()
+        .find_map(|(field, set)| set.then_some(field))
+    }
+
+    /// Checks constraints between options, once all of them have been set.
+    pub fn validate(&self) -> Result<(), OptionError> {
+        if self.version.is_some() && (self.min_version.is_some() || self.max_version.is_some()) {
+            return Err(OptionError::new(
+                "version",
+                "cannot be combined with `min_version` or `max_version`",
+            ));
+        }
+
+        if self.scale.is_some() && self.size.is_some() {
+            return Err(OptionError::new("scale", "cannot be combined with `size`"));
+        }
+
+        if let (Some(min), Some(max)) = (self.min_version, self.max_version) {
+            if min > max {
+                return Err(OptionError::new("min_version", "must not be greater than `max_version`"));
+            }
+        }
+
+        let versions = self.symbol.versions();
+        for (field, version) in [
+            ("version", self.version),
+            ("min_version", self.min_version),
+            ("max_version", self.max_version),
+        ] {
+            if version.is_some_and(|v| v > versions) {
+                return Err(OptionError::new(
+                    field,
+                    format!("expected a number between 1 and {} for this symbol", versions),
+                ));
+            }
+        }
+
+        if self.logo.is_some() && self.symbol != SymbolKind::QR {
+            return Err(OptionError::new("logo", "only QR symbols have room for a logo"));
+        }
+
+        if self.frame.is_some() && self.caption.is_some() {
+            return Err(OptionError::new("frame", "cannot be combined with `caption`"));
+        }
+
+        match (self.gradient, self.gradient_stops.is_empty()) {
+            (Some(_), true) => return Err(OptionError::new("gradient_stops", "required with `gradient`")),
+            (None, false) => return Err(OptionError::new("gradient", "required with `gradient_stops`")),
+            _ => {}
+        }
+
+        // Micro QR and rMQR rely on a single full finder pattern, which
+        // scanners fail to find when it is not square.
+        let shaped = |shape: Option<EyeShape>| shape.is_some_and(|shape| shape != EyeShape::Square);
+        for (field, shape) in [("eye_frame", self.eye_frame), ("eye_pupil", self.eye_pupil)] {
+            if shaped(shape) && self.symbol != SymbolKind::QR {
+                return Err(OptionError::new(field, "only QR symbols can have eyes other than square"));
+            }
+        }
+
+        match (self.symbol, self.ec) {
+            (SymbolKind::Micro, EcLevel::H) => Err(OptionError::new("ec", "Micro QR supports L, M and Q")),
+            (SymbolKind::RMQR, EcLevel::L | EcLevel::Q) => Err(OptionError::new("ec", "rMQR supports M and H")),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn parse_format(key: &str, value: &str) -> Result<OutputFormat, OptionError> {
+    OutputFormat::from_name(value).ok_or_else(|| {
+        let names: Vec<_> = OutputFormat::ALL.iter().map(|format| format.name()).collect();
+        let (last, rest) = names.split_last().unwrap();
+        OptionError::new(key, format!("expected one of {} or {}", rest.join(", "), last))
+    })
+}
+
+pub fn parse_page(key: &str, value: &str) -> Result<Page, OptionError> {
+    [Page::Fit, Page::A4, Page::Letter]
+        .into_iter()
+        .find(|page| page.name().eq_ignore_ascii_case(value))
+        .ok_or_else(|| OptionError::new(key, "expected one of fit, A4 or Letter"))
+}
+
+/// Parses a cell size such as `10x20`.
+fn parse_cell(key: &str, value: &str) -> Result<(usize, usize), OptionError> {
+    let error = || OptionError::new(key, "expected a width and a height between 1 and 100, such as 10x20");
+    let value = value.to_ascii_lowercase();
+    let (width, height) = value.split_once('x').ok_or_else(error)?;
+    match (parse_range(key, width, 1, 100), parse_range(key, height, 1, 100)) {
+        (Ok(width), Ok(height)) => Ok((width, height)),
+        _ => Err(error()),
+    }
+}
+
+fn parse_embed(key: &str, value: &str) -> Result<Embed, OptionError> {
+    match value.to_ascii_lowercase().as_str() {
+        "none" => Ok(Embed::None),
+        "data" => Ok(Embed::Data),
+        "img" => Ok(Embed::Img),
+        _ => Err(OptionError::new(key, "expected one of none, data or img")),
+    }
+}
+
+fn parse_text(key: &str, value: &str, max: usize) -> Result<String, OptionError> {
+    match value.trim().chars().count() {
+        0 => Err(OptionError::new(key, "expected some text")),
+        n if n > max => Err(OptionError::new(key, format!("expected at most {} characters", max))),
+        _ => Ok(value.to_string()),
+    }
+}
+
+fn parse_frame(key: &str, value: &str) -> Result<Option<FrameKind>, OptionError> {
+    match value.to_ascii_lowercase().as_str() {
+        "none" => Ok(None),
+        "banner" => Ok(Some(FrameKind::Banner)),
+        "badge" => Ok(Some(FrameKind::Badge)),
+        "bubble" => Ok(Some(FrameKind::Bubble)),
+        _ => Err(OptionError::new(key, "expected one of none, banner, badge or bubble")),
+    }
+}
+
+fn parse_caption_position(key: &str, value: &str) -> Result<CaptionPosition, OptionError> {
+    match value.to_ascii_lowercase().as_str() {
+        "below" => Ok(CaptionPosition::Below),
+        "above" => Ok(CaptionPosition::Above),
+        _ => Err(OptionError::new(key, "expected below or above")),
+    }
+}
+
+fn parse_style(key: &str, value: &str) -> Result<ModuleStyle, OptionError> {
+    ModuleStyle::ALL
+        .into_iter()
+        .find(|style| style.name().eq_ignore_ascii_case(value))
+        .ok_or_else(|| OptionError::new(key, "expected one of square, circle, rounded, liquid or diamond"))
+}
+
+fn parse_color(key: &str, value: &str) -> Result<Color, OptionError> {
+    Color::parse(value).map_err(|e| OptionError::new(key, e))
+}
+
+fn parse_gradient(key: &str, value: &str) -> Result<GradientKind, OptionError> {
+    match value.to_ascii_lowercase().as_str() {
+        "linear" => Ok(GradientKind::Linear),
+        "radial" => Ok(GradientKind::Radial),
+        _ => Err(OptionError::new(key, "expected linear or radial")),
+    }
+}
+
+/// Parses color stops such as `#000, navy 60%, rgb(0 0 128)`: colors with an
+/// optional position, separated by commas outside parentheses. As in CSS,
+/// the first and last stops default to 0% and 100%, and stops without a
+/// position are spread evenly between their neighbours.
+fn parse_stops(key: &str, value: &str) -> Result<Vec<(f64, Color)>, OptionError> {
+    let unbalanced = || OptionError::new(key, "expected balanced parentheses");
+    let mut items = Vec::new();
+    let (mut depth, mut start) = (0usize, 0);
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1).ok_or_else(unbalanced)?,
+            ',' if depth == 0 => {
+                items.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return Err(unbalanced());
+    }
+    items.push(&value[start..]);
+
+    if !(2..=MAX_GRADIENT_STOPS).contains(&items.len()) {
+        return Err(OptionError::new(
+            key,
+            format!("expected 2 to {} colors separated by commas", MAX_GRADIENT_STOPS),
+        ));
+    }
+
+    let mut stops = Vec::with_capacity(items.len());
+    for item in items {
+        let item = item.trim();
+        let (color, position) = match item.rsplit_once(char::is_whitespace) {
+            Some((color, position)) if position.ends_with('%') => {
+                let position = parse_range(key, position.trim_end_matches('%'), 0.0, 100.0)
+                    .map_err(|_| OptionError::new(key, format!("`{}` is not a position from 0% to 100%", position)))?;
+                (color, Some(position / 100.0))
+            }
+            _ => (item, None),
+        };
+        stops.push((position, parse_color(key, color)?));
+    }
+
+    let last = stops.len() - 1;
+    stops[0].0.get_or_insert(0.0);
+    stops[last].0.get_or_insert(1.0);
+    let mut previous = 0;
+    for i in 1..=last {
+        let Some(position) = stops[i].0 else { continue };
+        let from = stops[previous].0.unwrap();
+        if position < from {
+            return Err(OptionError::new(key, "positions must not decrease"));
+        }
+        let span = (i - previous) as f64;
+        for (k, stop) in stops[previous + 1..i].iter_mut().enumerate() {
+            stop.0 = Some(from + (position - from) * (k + 1) as f64 / span);
+        }
+        previous = i;
+    }
+
+    Ok(stops.into_iter().map(|(position, color)| (position.unwrap(), color)).collect())
+}
+
+fn parse_eye_frame(key: &str, value: &str) -> Result<EyeShape, OptionError> {
+    match value.to_ascii_lowercase().as_str() {
+        "square" => Ok(EyeShape::Square),
+        "rounded" => Ok(EyeShape::Rounded),
+        "circle" => Ok(EyeShape::Circle),
+        _ => Err(OptionError::new(key, "expected one of square, rounded or circle")),
+    }
+}
+
+fn parse_eye_pupil(key: &str, value: &str) -> Result<EyeShape, OptionError> {
+    match value.to_ascii_lowercase().as_str() {
+        "diamond" => Ok(EyeShape::Diamond),
+        _ => parse_eye_frame(key, value)
+            .map_err(|_| OptionError::new(key, "expected one of square, rounded, circle or diamond")),
+    }
+}
+
+fn parse_symbol(key: &str, value: &str) -> Result<SymbolKind, OptionError> {
+    SymbolKind::ALL
+        .into_iter()
+        .find(|kind| kind.name().eq_ignore_ascii_case(value))
+        .ok_or_else(|| OptionError::new(key, "expected one of qr, micro or rmqr"))
+}
+
+fn parse_ec(key: &str, value: &str) -> Result<EcLevel, OptionError> {
+    match value.to_ascii_uppercase().as_str() {
+        "L" => Ok(EcLevel::L),
+        "M" => Ok(EcLevel::M),
+        "Q" => Ok(EcLevel::Q),
+        "H" => Ok(EcLevel::H),
+        _ => Err(OptionError::new(key, "expected one of L, M, Q or H")),
+    }
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, OptionError> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Ok(true),
+        "false" | "0" | "no" | "off" => Ok(false),
+        _ => Err(OptionError::new(key, "expected true or false")),
+    }
+}
+
+/// Parses a number of pixels, optionally suffixed with `px`, or a physical
+/// length suffixed with `mm` or `in`.
+pub fn parse_length(key: &str, value: &str) -> Result<Length, OptionError> {
+    let value = value.to_ascii_lowercase();
+    let physical = |number: &str, unit: &str, max: f64| match number.parse::<f64>() {
+        Ok(n) if n > 0.0 && n <= max => Ok(n),
+        _ => Err(OptionError::new(key, format!("expected a length above 0{unit} and up to {max}{unit}"))),
+    };
+
+    if let Some(mm) = value.strip_suffix("mm") {
+        Ok(Length::Millimetres(physical(mm, "mm", MAX_PHYSICAL_MM)?))
+    } else if let Some(inches) = value.strip_suffix("in") {
+        Ok(Length::Inches(physical(inches, "in", MAX_PHYSICAL_MM / 25.4)?))
+    } else {
+        let pixels = value.strip_suffix("px").unwrap_or(&value);
+        Ok(Length::Pixels(parse_range(key, pixels, 1, MAX_DIMENSION)?))
+    }
+}
+
+pub fn parse_range<T>(key: &str, value: &str, min: T, max: T) -> Result<T, OptionError>
+where
+    T: std::str::FromStr + PartialOrd + fmt::Display,
+{
+    match value.parse::<T>() {
+        Ok(n) if n >= min && n <= max => Ok(n),
+        _ => Err(OptionError::new(key, format!("expected a number between {} and {}", min, max))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stops(value: &str) -> Result<Vec<(f64, Color)>, String> {
+        parse_stops("gradient_stops", value).map_err(|e| e.message)
+    }
+
+    /// Positions of the stops, rounded to hide floating point error.
+    fn positions(value: &str) -> Vec<f64> {
+        stops(value).unwrap().into_iter().map(|(position, _)| (position * 1e6).round() / 1e6).collect()
+    }
+
+    #[test]
+    fn parses_stop_colors() {
+        let red = Color::rgb(255, 0, 0);
+        assert_eq!(stops("red, #0000ff"), Ok(vec![(0.0, red), (1.0, Color::rgb(0, 0, 255))]));
+        assert_eq!(
+            stops("rgb(255, 0, 0) 20%, rgba(0, 0, 0, 0.5)"),
+            Ok(vec![(0.2, red), (1.0, Color { r: 0, g: 0, b: 0, a: 128 })])
+        );
+        assert_eq!(stops("rgb(0 0 0 / 50%), red").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn interpolates_missing_positions() {
+        assert_eq!(positions("red, green, blue"), [0.0, 0.5, 1.0]);
+        assert_eq!(positions("red 20%, green, blue, white 80%"), [0.2, 0.4, 0.6, 0.8]);
+        assert_eq!(positions("red, green 10%, blue, white"), [0.0, 0.1, 0.55, 1.0]);
+        assert_eq!(positions("red 30%, green 30%"), [0.3, 0.3]);
+    }
+
+    #[test]
+    fn rejects_invalid_stops() {
+        for invalid in [
+            "red",
+            "red, green, blue, white, black, gray, navy, teal, lime",
+            "red 50%, green 20%",
+            "red, green 120%",
+            "red, green -5%",
+            "red, nope",
+            "red, ",
+        ] {
+            assert!(stops(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn rejects_unbalanced_parentheses() {
+        assert_eq!(stops("red), green"), Err("expected balanced parentheses".into()));
+        assert_eq!(stops("rgb(0, 0, 0, red"), Err("expected balanced parentheses".into()));
+        assert_eq!(stops("rgb(0, 0, 0)), (red"), Err("expected balanced parentheses".into()));
+    }
+}
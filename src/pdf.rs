@@ -0,0 +1,97 @@
+use std::fmt::Write;
+
+/// A minimal PDF 1.4 writer, with just what is needed for pages of vector
+/// graphics: objects, streams, pages and the cross-reference table.
+pub struct Document {
+    /// Object bodies, numbered from 1. The catalog and the page tree are 1
+    /// and 2, and are only written by `finish`.
+    objects: Vec<Vec<u8>>,
+    pages: Vec<usize>,
+}
+
+const CATALOG: usize = 1;
+const PAGES: usize = 2;
+
+impl Document {
+    pub fn new() -> Self {
+        Document {
+            objects: vec![Vec::new(), Vec::new()],
+            pages: Vec::new(),
+        }
+    }
+
+    /// Adds an object and returns its number.
+    pub fn add(&mut self, object: impl Into<Vec<u8>>) -> usize {
+        self.objects.push(object.into());
+        self.objects.len()
+    }
+
+    /// Adds a stream object. `dictionary` holds extra entries besides
+    /// `/Length`, each preceded by a space.
+    pub fn add_stream(&mut self, dictionary: &str, data: &[u8]) -> usize {
+        let mut object = format!("<< /Length {}{} >>\nstream\n", data.len(), dictionary).into_bytes();
+        object.extend_from_slice(data);
+        object.extend_from_slice(b"\nendstream");
+        self.add(object)
+    }
+
+    /// Adds a page of `width` by `height` points, drawn by `content` with
+    /// `resources`, a resource dictionary body such as `/ExtGState << ... >>`.
+    pub fn add_page(&mut self, width: f64, height: f64, resources: &str, content: &str) {
+        let contents = self.add_stream("", content.as_bytes());
+        let page = self.add(format!(
+            "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] /Resources << {} >> /Contents {} 0 R >>",
+            PAGES,
+            number(width),
+            number(height),
+            resources,
+            contents
+        ));
+        self.pages.push(page);
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.objects[CATALOG - 1] = format!("<< /Type /Catalog /Pages {} 0 R >>", PAGES).into_bytes();
+        let kids: Vec<_> = self.pages.iter().map(|page| format!("{} 0 R", page)).collect();
+        self.objects[PAGES - 1] =
+            format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), self.pages.len()).into_bytes();
+
+        // The binary comment tells transfer tools not to treat the file as text.
+        let mut pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+        let mut offsets = Vec::with_capacity(self.objects.len());
+        for (i, object) in self.objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            pdf.extend_from_slice(object);
+            pdf.extend_from_slice(b"\nendobj\n");
+        }
+
+        let xref = pdf.len();
+        let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", self.objects.len() + 1);
+        for offset in offsets {
+            writeln!(trailer, "{:010} 00000 n ", offset).unwrap();
+        }
+        write!(
+            trailer,
+            "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.objects.len() + 1,
+            CATALOG,
+            xref
+        )
+        .unwrap();
+        pdf.extend_from_slice(trailer.as_bytes());
+
+        pdf
+    }
+}
+
+/// Formats a coordinate or a color component for PDF and PostScript, which
+/// take neither exponents nor superfluous digits.
+pub fn number(n: f64) -> String {
+    let s = format!("{:.4}", n);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    match s {
+        "-0" => "0".into(),
+        s => s.into(),
+    }
+}
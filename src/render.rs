@@ -0,0 +1,1482 @@
+use std::fmt::Write;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use image::codecs::jpeg::{JpegEncoder, PixelDensity};
+use image::imageops::FilterType;
+use image::{ExtendedColorType, ImageBuffer, ImageFormat, Luma, Pixel, Rgb, Rgba};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde::Serialize;
+use png::{BitDepth, ColorType, Encoder, PixelDimensions, Unit};
+
+use crate::caption::{self, Caption};
+use crate::color::{self, Color};
+use crate::embed::{self, escape_html};
+use crate::error::Error;
+use crate::frame::{Fill, Frame, Outline};
+use crate::logo::Logo;
+use crate::options::{CaptionPosition, EyeShape, FrameKind, GradientKind, Length, ModuleStyle, Options, DEFAULT_DPI};
+use crate::pdf::{self, Document};
+use crate::shape::{self, Eye, Shape};
+use crate::symbol::{Function, Symbol};
+
+/// Size of the longest side of the rendered image when neither `scale` nor
+/// `size` is given, in pixels. The image is at least this large.
+const DEFAULT_DIMENSION: usize = 1000;
+
+/// Hard limit on either side of the rendered image, in pixels, whatever the
+/// options say.
+pub const MAX_DIMENSION: usize = 8192;
+
+/// Smallest contrast ratio allowed between the colors, as recommended by WCAG
+/// 2.1 for graphical objects.
+const MIN_CONTRAST: f64 = 3.0;
+
+/// Width of the quiet zone in modules: `margin`, or what the symbol's
+/// specification requires.
+fn quiet_zone(symbol: &Symbol, options: &Options) -> usize {
+    options.margin.unwrap_or(symbol.kind.quiet_zone())
+}
+
+/// Largest share of the data modules that a logo may cover. Level H restores
+/// up to 30% of the codewords, and the edges of the logo damage codewords
+/// that it only partly covers.
+const MAX_LOGO_COVERAGE: f64 = 0.15;
+
+/// Smallest side of the zone cleared for a logo, in modules. The logo is
+/// inset by half a module on every side, so it always keeps some pixels.
+const MIN_LOGO_MODULES: usize = 3;
+
+/// Pixels per module of the image that logos are checked against.
+const VERIFY_SCALE: usize = 4;
+
+/// A square of modules, such as the one left light behind a logo.
+#[derive(Clone, Copy)]
+struct Zone {
+    left: usize,
+    top: usize,
+    size: usize,
+}
+
+impl Zone {
+    fn contains(&self, x: usize, y: usize) -> bool {
+        (self.left..self.left + self.size).contains(&x) && (self.top..self.top + self.size).contains(&y)
+    }
+}
+
+/// Clears a square in the middle of the symbol for the logo, as large as
+/// `logo_size` asks for but at least `MIN_LOGO_MODULES`, with the same parity
+/// as the symbol so that it stays centred on a module boundary or in the
+/// middle of a module.
+fn logo_zone(symbol: &Symbol, options: &Options) -> Result<Zone, Error> {
+    let mut size = ((symbol.width as f64 * options.logo_size).round() as usize).max(MIN_LOGO_MODULES);
+    if (symbol.width - size) % 2 == 1 {
+        size += 1;
+    }
+    let zone = Zone {
+        left: (symbol.width - size) / 2,
+        top: (symbol.height - size) / 2,
+        size,
+    };
+
+    let data = |x: usize, y: usize| symbol.function(x, y) == Function::Data;
+    let modules = || (0..symbol.height).flat_map(|y| (0..symbol.width).map(move |x| (x, y)));
+    let total = modules().filter(|&(x, y)| data(x, y)).count();
+    let covered = modules().filter(|&(x, y)| data(x, y) && zone.contains(x, y)).count();
+    if covered as f64 > total as f64 * MAX_LOGO_COVERAGE {
+        return Err(Error::Capacity(format!(
+            "the logo would cover {:.0}% of the data modules, more than error correction can restore; \
+             use a smaller `logo_size` or a larger version",
+            covered as f64 * 100.0 / total as f64
+        )));
+    }
+
+    Ok(zone)
+}
+
+/// Eyes for the finder patterns, if any of the `eye_` options are set.
+fn eyes(symbol: &Symbol, options: &Options) -> Vec<(usize, usize, Eye)> {
+    if !options.has_eyes() {
+        return Vec::new();
+    }
+
+    let frame = options.eye_frame.unwrap_or(EyeShape::Square);
+    let pupil = options.eye_pupil.unwrap_or(EyeShape::Square);
+    symbol
+        .finder_patterns()
+        .into_iter()
+        .map(|(x, y, modules)| (x, y, Eye::new(frame, pupil, modules)))
+        .collect()
+}
+
+/// Where a gradient runs, in pixels.
+#[derive(Clone, Copy)]
+enum Spread {
+    /// From the first point, at 0, to the second, at 1.
+    Linear((f64, f64), (f64, f64)),
+    /// From a centre, at 0, to a circle of the given radius, at 1.
+    Radial((f64, f64), f64),
+}
+
+impl Spread {
+    /// Spread of a gradient across the symbol, without its quiet zone. Linear
+    /// gradients reach the corners, as in CSS.
+    fn new(symbol: &Symbol, layout: &Layout, kind: GradientKind, angle: f64) -> Self {
+        let (left, top) = layout.pixel(0, 0);
+        let (width, height) = ((symbol.width * layout.module_size) as f64, (symbol.height * layout.module_size) as f64);
+        let (cx, cy) = (left as f64 + width / 2.0, top as f64 + height / 2.0);
+        match kind {
+            GradientKind::Linear => {
+                let (dx, dy) = (angle.to_radians().sin(), -angle.to_radians().cos());
+                let half = (width * dx.abs() + height * dy.abs()) / 2.0;
+                Spread::Linear((cx - dx * half, cy - dy * half), (cx + dx * half, cy + dy * half))
+            }
+            GradientKind::Radial => Spread::Radial((cx, cy), width.hypot(height) / 2.0),
+        }
+    }
+
+    /// Position of the point at `x`, `y` along the gradient, from 0 to 1.
+    fn position(self, x: f64, y: f64) -> f64 {
+        match self {
+            Spread::Linear((x1, y1), (x2, y2)) => {
+                let (dx, dy) = (x2 - x1, y2 - y1);
+                (((x - x1) * dx + (y - y1) * dy) / (dx * dx + dy * dy)).clamp(0.0, 1.0)
+            }
+            Spread::Radial((cx, cy), radius) => ((x - cx).hypot(y - cy) / radius).min(1.0),
+        }
+    }
+}
+
+/// What part of the image is painted with.
+#[derive(Clone)]
+enum Paint<T> {
+    Solid(T),
+    /// Colors at positions from 0 to 1 along the spread.
+    Gradient(Spread, Vec<(f64, T)>),
+}
+
+impl<T: Copy> Paint<T> {
+    fn colors(&self) -> Vec<T> {
+        match self {
+            Paint::Solid(color) => vec![*color],
+            Paint::Gradient(_, stops) => stops.iter().map(|&(_, color)| color).collect(),
+        }
+    }
+
+    fn map<P>(&self, f: impl Fn(T) -> P) -> Paint<P> {
+        match self {
+            Paint::Solid(color) => Paint::Solid(f(*color)),
+            Paint::Gradient(spread, stops) => {
+                Paint::Gradient(*spread, stops.iter().map(|&(position, color)| (position, f(color))).collect())
+            }
+        }
+    }
+}
+
+impl<P: Pixel<Subpixel = u8>> Paint<P> {
+    /// Color of the pixel at `x`, `y`, taken at its centre.
+    fn at(&self, x: usize, y: usize) -> P {
+        let (spread, stops) = match self {
+            Paint::Solid(color) => return *color,
+            Paint::Gradient(spread, stops) => (spread, stops),
+        };
+
+        let t = spread.position(x as f64 + 0.5, y as f64 + 0.5);
+        let i = stops.iter().position(|&(position, _)| position >= t).unwrap_or(stops.len() - 1);
+        match i {
+            0 => stops[0].1,
+            _ => {
+                let ((from, first), (to, second)) = (stops[i - 1], stops[i]);
+                match to > from {
+                    true => mix(second, first, ((t - from) / (to - from)) as f32),
+                    false => second,
+                }
+            }
+        }
+    }
+}
+
+/// Paints of the background and of everything drawn on it, either in the
+/// colors given or in pixels of the image being rendered.
+#[derive(Clone)]
+struct Palette<T> {
+    fg: Paint<T>,
+    bg: T,
+    /// Paints of the rings and of the pupils of eyes, which follow `fg`
+    /// unless they have colors of their own.
+    ring: Paint<T>,
+    pupil: Paint<T>,
+    /// Colors of the frame and of its text, when there is one.
+    frame: Option<(T, T)>,
+}
+
+impl Palette<Color> {
+    fn new(symbol: &Symbol, layout: &Layout, options: &Options) -> Self {
+        let fg = match options.gradient {
+            Some(kind) => {
+                let spread = Spread::new(symbol, layout, kind, options.gradient_angle);
+                Paint::Gradient(spread, options.gradient_stops.clone())
+            }
+            None => Paint::Solid(options.fg),
+        };
+        let own = |color: Option<Color>| color.map_or_else(|| fg.clone(), Paint::Solid);
+
+        Palette {
+            ring: own(options.eye_frame_color),
+            pupil: own(options.eye_pupil_color),
+            fg,
+            bg: options.bg,
+            frame: options.frame.map(|_| frame_colors(options)),
+        }
+    }
+
+    /// The opaque colors everything will actually look like, as with
+    /// `color::flatten`.
+    fn flatten(&self) -> Self {
+        let bg = self.bg.over(Color::WHITE);
+        Palette {
+            bg,
+            ..self.map(|color| color.over(bg))
+        }
+    }
+
+    fn colors(&self) -> Vec<Color> {
+        let mut colors = vec![self.bg];
+        colors.extend(self.fg.colors());
+        colors.extend(self.ring.colors());
+        colors.extend(self.pupil.colors());
+        colors.extend(self.frame.iter().flat_map(|&(frame, text)| [frame, text]));
+        colors
+    }
+
+    fn map<P>(&self, f: impl Fn(Color) -> P) -> Palette<P> {
+        Palette {
+            fg: self.fg.map(&f),
+            bg: f(self.bg),
+            ring: self.ring.map(&f),
+            pupil: self.pupil.map(&f),
+            frame: self.frame.map(|(frame, text)| (f(frame), f(text))),
+        }
+    }
+}
+
+/// Pixel geometry of a rendered symbol.
+struct Layout {
+    /// Resolution in dots per inch, when the image has a physical size.
+    dpi: Option<u32>,
+    quiet_zone: usize,
+    module_size: usize,
+    width: usize,
+    height: usize,
+    /// Padding around the quiet zone, in pixels.
+    left: usize,
+    top: usize,
+    caption: Option<Caption>,
+    frame: Option<Frame>,
+    /// Modules left light for the logo, and the logo itself.
+    clear: Option<Zone>,
+    logo: Option<Arc<Logo>>,
+    style: ModuleStyle,
+    style_finders: bool,
+    /// Finder patterns drawn as eyes, by their top-left module.
+    eyes: Vec<(usize, usize, Eye)>,
+}
+
+impl Layout {
+    /// Picks a whole number of pixels per module, from `scale`, or from `size`
+    /// rounded down, or large enough to reach the default dimension. The
+    /// result is then shrunk to fit `max_size`, while going over the hard
+    /// limit is an error.
+    fn new(symbol: &Symbol, options: &Options) -> Result<Self, Error> {
+        Self::with_default_scale(symbol, options, None)
+    }
+
+    /// Like `new`, with a different number of pixels per module when neither
+    /// `scale` nor `size` is given.
+    fn with_default_scale(symbol: &Symbol, options: &Options, default: Option<usize>) -> Result<Self, Error> {
+        let dpi = options.resolution();
+        let pixels = |length: Length| length.pixels(dpi.unwrap_or_default());
+        let quiet_zone = quiet_zone(symbol, options);
+        let longest = symbol.width.max(symbol.height) + 2 * quiet_zone;
+        let too_small = |pixels| {
+            Error::Size(format!(
+                "symbol is {} modules across with its quiet zone, which does not fit in {} pixels",
+                longest, pixels
+            ))
+        };
+
+        let mut module_size = match (options.scale, options.size) {
+            (Some(scale), _) => scale,
+            (None, Some(size)) if pixels(size) < longest => return Err(too_small(pixels(size))),
+            (None, Some(size)) => pixels(size) / longest,
+            (None, None) => default.unwrap_or(DEFAULT_DIMENSION.div_ceil(longest)),
+        };
+
+        if let Some(max_size) = options.max_size.map(pixels) {
+            module_size = module_size.min(max_size / longest);
+            if module_size == 0 {
+                return Err(too_small(max_size));
+            }
+        }
+
+        if longest * module_size > options.max_dimension {
+            return Err(Error::Size(format!(
+                "image would be {} pixels across, above the limit of {}",
+                longest * module_size,
+                options.max_dimension
+            )));
+        }
+
+        Ok(Layout {
+            dpi,
+            quiet_zone,
+            module_size,
+            width: (symbol.width + 2 * quiet_zone) * module_size,
+            height: (symbol.height + 2 * quiet_zone) * module_size,
+            left: 0,
+            top: 0,
+            caption: None,
+            frame: None,
+            clear: options.logo.as_ref().map(|_| logo_zone(symbol, options)).transpose()?,
+            logo: options.logo.clone(),
+            style: options.style,
+            style_finders: options.style_finders,
+            eyes: eyes(symbol, options),
+        })
+    }
+
+    /// Makes room for the caption, if there is one, above or below the quiet
+    /// zone. Text is inset from the sides by a module.
+    fn add_caption(&mut self, options: &Options) -> Result<(), Error> {
+        let Some(text) = &options.caption else {
+            return Ok(());
+        };
+
+        let mut caption = Caption::new(text, self.width, self.module_size);
+        match options.caption_position {
+            CaptionPosition::Below => caption.top = self.height,
+            CaptionPosition::Above => self.top += caption.height,
+        }
+        self.height += caption.height;
+        self.caption = Some(caption);
+
+        if self.height > options.max_dimension {
+            return Err(Error::Size(format!(
+                "image would be {} pixels high with the caption, above the limit of {}",
+                self.height, options.max_dimension
+            )));
+        }
+        Ok(())
+    }
+
+    /// Puts the frame, if there is one, around the quiet zone.
+    fn add_frame(&mut self, options: &Options) -> Result<(), Error> {
+        let Some(kind) = options.frame else {
+            return Ok(());
+        };
+
+        let frame = Frame::new(kind, &options.frame_text, self.width, self.height, self.module_size);
+        self.left += frame.left;
+        self.top += frame.top;
+        (self.width, self.height) = (frame.width, frame.height);
+        self.frame = Some(frame);
+
+        if self.width.max(self.height) > options.max_dimension {
+            return Err(Error::Size(format!(
+                "image would be {} by {} pixels with the frame, above the limit of {}",
+                self.width, self.height, options.max_dimension
+            )));
+        }
+        Ok(())
+    }
+
+    /// Pads the image to a whole number of `cell`s, keeping the symbol in the
+    /// middle.
+    fn pad_to(&mut self, (cell_width, cell_height): (usize, usize)) {
+        let (width, height) = (self.width.next_multiple_of(cell_width), self.height.next_multiple_of(cell_height));
+        self.left = (width - self.width) / 2;
+        self.top = (height - self.height) / 2;
+        (self.width, self.height) = (width, height);
+    }
+
+    /// Top-left pixel of the module at `x`, `y`, counted from the top-left of
+    /// the symbol.
+    fn pixel(&self, x: usize, y: usize) -> (usize, usize) {
+        (
+            self.left + (x + self.quiet_zone) * self.module_size,
+            self.top + (y + self.quiet_zone) * self.module_size,
+        )
+    }
+
+    /// Whether the module at `x`, `y` is drawn dark, which it is not behind
+    /// the logo.
+    fn is_dark(&self, symbol: &Symbol, x: usize, y: usize) -> bool {
+        symbol.is_dark(x, y) && !self.clear.is_some_and(|zone| zone.contains(x, y))
+    }
+
+    /// Pixel rectangle of the logo: the cleared zone less half a module on
+    /// every side, as left, top and size.
+    fn logo_box(&self) -> Option<(usize, usize, usize)> {
+        let zone = self.clear?;
+        let (left, top) = self.pixel(zone.left, zone.top);
+        let inset = self.module_size / 2;
+        Some((left + inset, top + inset, (zone.size * self.module_size).saturating_sub(2 * inset)))
+    }
+
+    /// Shape of the dark module at `x`, `y`. Finder patterns stay square
+    /// unless `style_finders` is set, and liquid modules are rounded at the
+    /// corners where neither neighbour is dark.
+    fn shape(&self, symbol: &Symbol, x: usize, y: usize) -> Shape {
+        if symbol.function(x, y) == Function::Finder && !self.style_finders {
+            return Shape::SQUARE;
+        }
+
+        match self.style {
+            ModuleStyle::Square => Shape::SQUARE,
+            ModuleStyle::Circle => Shape::CIRCLE,
+            ModuleStyle::Rounded => Shape::Rounded([shape::ROUNDED_RADIUS; 4]),
+            ModuleStyle::Diamond => Shape::Diamond,
+            ModuleStyle::Liquid => {
+                let dark = |dx: isize, dy: isize| {
+                    let (x, y) = (x.wrapping_add_signed(dx), y.wrapping_add_signed(dy));
+                    x < symbol.width && y < symbol.height && self.is_dark(symbol, x, y)
+                };
+                let (left, right, above, below) = (dark(-1, 0), dark(1, 0), dark(0, -1), dark(0, 1));
+                let radius = |round: bool| if round { 0.5 } else { 0.0 };
+                Shape::Rounded([
+                    radius(!left && !above),
+                    radius(!right && !above),
+                    radius(!right && !below),
+                    radius(!left && !below),
+                ])
+            }
+        }
+    }
+
+    /// Whether the module at `x`, `y` is part of an eye, and so not drawn on
+    /// its own.
+    fn in_eye(&self, x: usize, y: usize) -> bool {
+        self.eyes.iter().any(|&(left, top, eye)| {
+            (left..left + eye.modules).contains(&x) && (top..top + eye.modules).contains(&y)
+        })
+    }
+
+    /// Iterates over the top-left pixel of every dark module, with its shape,
+    /// leaving out the eyes.
+    fn dark_modules<'a>(&'a self, symbol: &'a Symbol) -> impl Iterator<Item = (usize, usize, Shape)> + 'a {
+        (0..symbol.height)
+            .flat_map(move |y| (0..symbol.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.is_dark(symbol, x, y) && !self.in_eye(x, y))
+            .map(|(x, y)| {
+                let (left, top) = self.pixel(x, y);
+                (left, top, self.shape(symbol, x, y))
+            })
+    }
+
+    /// Iterates over horizontal runs of dark modules, as their top-left pixel
+    /// and their width in pixels. Vector formats draw these rather than every
+    /// module.
+    fn dark_runs<'a>(&'a self, symbol: &'a Symbol) -> impl Iterator<Item = (usize, usize, usize)> + 'a {
+        (0..symbol.height).flat_map(move |y| {
+            let mut runs = Vec::new();
+            let mut x = 0;
+            while x < symbol.width {
+                let start = x;
+                while x < symbol.width && self.is_dark(symbol, x, y) {
+                    x += 1;
+                }
+                if x > start {
+                    let (left, top) = self.pixel(start, y);
+                    runs.push((left, top, (x - start) * self.module_size));
+                }
+                x += 1;
+            }
+            runs
+        })
+    }
+
+    /// Size of a pixel in points, for vector formats. The symbol is exactly as
+    /// large as a physical `size` asks for, and otherwise as large as the
+    /// raster formats print at their resolution.
+    fn point_size(&self, options: &Options) -> f64 {
+        let dpi = self.dpi.unwrap_or(DEFAULT_DPI);
+        match options.size {
+            Some(size) if size.is_physical() => {
+                let longest = size.points(dpi).min(options.max_size.map_or(f64::INFINITY, |max| max.points(dpi)));
+                longest / self.width.max(self.height) as f64
+            }
+            _ => 72.0 / dpi as f64,
+        }
+    }
+}
+
+/// The color the foreground is judged by: `fg`, or the lightest stop of the
+/// gradient, since a gradient is only as dark as that.
+fn foreground(options: &Options) -> Color {
+    let luminance = |color| color::flatten(color, options.bg).0.luminance();
+    match options.gradient {
+        Some(_) => options
+            .gradient_stops
+            .iter()
+            .map(|&(_, color)| color)
+            .max_by(|&a, &b| luminance(a).total_cmp(&luminance(b)))
+            .unwrap_or(options.fg),
+        None => options.fg,
+    }
+}
+
+/// Contrast ratio between the foreground and the background, as reported to
+/// clients.
+pub fn contrast_ratio(options: &Options) -> f64 {
+    color::contrast_ratio(foreground(options), options.bg)
+}
+
+/// Colors of the frame and of its text, which default to `fg` and to `bg` on
+/// white.
+fn frame_colors(options: &Options) -> (Color, Color) {
+    let text = options.frame_text_color.unwrap_or_else(|| options.bg.over(Color::WHITE));
+    (options.frame_color.unwrap_or(options.fg), text)
+}
+
+/// Rejects colors that scanners are likely to struggle with, unless the
+/// request insists with `force`.
+pub fn check_contrast(options: &Options) -> Result<(), Error> {
+    if options.force {
+        return Ok(());
+    }
+
+    // Eyes are checked like the modules, since scanners look for them first.
+    let fg = match options.gradient {
+        Some(_) => "gradient_stops",
+        None => "fg",
+    };
+    let inks = [
+        (fg, Some(foreground(options))),
+        ("eye_frame_color", options.eye_frame_color),
+        ("eye_pupil_color", options.eye_pupil_color),
+    ];
+    for (field, ink) in inks {
+        let Some(ink) = ink else { continue };
+        let (fg, bg) = color::flatten(ink, options.bg);
+        if fg.luminance() > bg.luminance() {
+            return Err(Error::Contrast(format!(
+                "`{}` is lighter than `bg`, and many scanners cannot read inverted codes; \
+                 swap the colors or pass force=true",
+                field
+            )));
+        }
+
+        let ratio = color::contrast_ratio(fg, bg);
+        if ratio < MIN_CONTRAST {
+            return Err(Error::Contrast(format!(
+                "contrast ratio between `{}` and `bg` is {:.2}:1, below the minimum of {}:1; \
+                 pick darker or lighter colors or pass force=true",
+                field, ratio, MIN_CONTRAST
+            )));
+        }
+    }
+
+    // The text of the frame is printed on it, either way round.
+    if options.frame.is_some() {
+        let (frame, text) = frame_colors(options);
+        let ratio = color::contrast_ratio(text, frame);
+        if ratio < MIN_CONTRAST {
+            return Err(Error::Contrast(format!(
+                "contrast ratio between `frame_text_color` and `frame_color` is {:.2}:1, below the minimum of {}:1; \
+                 pick darker or lighter colors or pass force=true",
+                ratio, MIN_CONTRAST
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn rasterize<P: Pixel<Subpixel = u8>>(symbol: &Symbol, layout: &Layout, palette: Palette<P>) -> ImageBuffer<P, Vec<u8>> {
+    let Palette {
+        fg,
+        bg,
+        ring,
+        pupil,
+        frame: frame_colors,
+    } = palette;
+    let mut image = ImageBuffer::from_pixel(layout.width as u32, layout.height as u32, bg);
+
+    if let (Some(frame), Some((color, _))) = (&layout.frame, frame_colors) {
+        for (outline, fill) in &frame.outlines {
+            let color = match fill {
+                Fill::Frame => color,
+                Fill::Background => bg,
+            };
+            outline.draw(|x, y, coverage| image.put_pixel(x, y, mix(color, *image.get_pixel(x, y), coverage)));
+        }
+    }
+
+    // Shapes other than squares are antialiased like text.
+    let size = layout.module_size;
+    for (left, top, shape) in layout.dark_modules(symbol) {
+        for y in 0..size {
+            for x in 0..size {
+                let color = fg.at(left + x, top + y);
+                let pixel = match shape {
+                    Shape::SQUARE => color,
+                    _ => mix(color, bg, shape.coverage(x, y, size)),
+                };
+                image.put_pixel((left + x) as u32, (top + y) as u32, pixel);
+            }
+        }
+    }
+
+    for &(x, y, eye) in &layout.eyes {
+        let (left, top) = layout.pixel(x, y);
+        for y in 0..eye.modules * size {
+            for x in 0..eye.modules * size {
+                let (ring_coverage, pupil_coverage) = eye.coverage(x, y, size);
+                let (ring, pupil) = (ring.at(left + x, top + y), pupil.at(left + x, top + y));
+                let pixel = mix(pupil, mix(ring, bg, ring_coverage), pupil_coverage);
+                image.put_pixel((left + x) as u32, (top + y) as u32, pixel);
+            }
+        }
+    }
+
+    if let (Some(logo), Some((left, top, size))) = (&layout.logo, layout.logo_box()) {
+        draw_logo(&mut image, logo, left, top, size);
+    }
+
+    // Text is antialiased by mixing the colors with the glyph coverage.
+    if let Some(caption) = &layout.caption {
+        caption.draw(layout.width, |x, y, coverage| {
+            if x < image.width() && y < image.height() {
+                image.put_pixel(x, y, mix(fg.at(x as usize, y as usize), bg, coverage));
+            }
+        });
+    }
+    if let (Some(frame), Some((_, color))) = (&layout.frame, frame_colors) {
+        frame.draw_text(|x, y, coverage| {
+            if x < image.width() && y < image.height() {
+                image.put_pixel(x, y, mix(color, *image.get_pixel(x, y), coverage));
+            }
+        });
+    }
+
+    image
+}
+
+/// Mixes the colors in proportion to `coverage`, the share of the pixel taken
+/// by the foreground.
+fn mix<P: Pixel<Subpixel = u8>>(fg: P, bg: P, coverage: f32) -> P {
+    fg.map2(&bg, |f, b| (b as f32 + (f as f32 - b as f32) * coverage.min(1.0)).round() as u8)
+}
+
+/// Scales the logo to fit a square of `size` pixels, keeping its aspect ratio,
+/// and composites it in the middle of the square. Images without an alpha
+/// channel get the logo blended with what is underneath.
+fn draw_logo<P: Pixel<Subpixel = u8>>(
+    image: &mut ImageBuffer<P, Vec<u8>>,
+    logo: &Logo,
+    left: usize,
+    top: usize,
+    size: usize,
+) {
+    if size == 0 {
+        return;
+    }
+    let (width, height) = (logo.image.width() as f64, logo.image.height() as f64);
+    let scale = size as f64 / width.max(height);
+    let fit = |side: f64| ((side * scale).round() as u32).clamp(1, size as u32);
+    let (width, height) = (fit(width), fit(height));
+    let scaled = image::imageops::resize(&logo.image, width, height, FilterType::Lanczos3);
+    let (left, top) = (left as u32 + (size as u32 - width) / 2, top as u32 + (size as u32 - height) / 2);
+
+    for (x, y, source) in scaled.enumerate_pixels() {
+        let Rgba([r, g, b, a]) = *source;
+        let pixel = image.get_pixel_mut(left + x, top + y);
+        let channels = pixel.channels_mut();
+        let alpha = a as f64 / 255.0;
+        let below = match channels.len() {
+            4 => channels[3] as f64 / 255.0,
+            _ => 1.0,
+        };
+        let out = alpha + below * (1.0 - alpha);
+        if out == 0.0 {
+            continue;
+        }
+        for (channel, value) in channels.iter_mut().zip([r, g, b]) {
+            *channel = ((value as f64 * alpha + *channel as f64 * below * (1.0 - alpha)) / out).round() as u8;
+        }
+        if channels.len() == 4 {
+            channels[3] = (out * 255.0).round() as u8;
+        }
+    }
+}
+
+/// Checks that the symbol still decodes to `content` with the logo over it,
+/// from a small rendering with the colors as they would look on white. Eyes
+/// are drawn square, since the decoder only finds square finder patterns
+/// while phones find the others too.
+pub fn verify(symbol: &Symbol, content: &str, options: &Options) -> Result<(), Error> {
+    let layout = Layout::with_default_scale(
+        symbol,
+        &Options {
+            scale: Some(VERIFY_SCALE),
+            size: None,
+            max_size: None,
+            caption: None,
+            eye_frame: None,
+            eye_pupil: None,
+            ..options.clone()
+        },
+        None,
+    )?;
+    let palette = Palette::new(symbol, &layout, options).flatten().map(|c| Rgb([c.r, c.g, c.b]));
+    let image = rasterize(symbol, &layout, palette);
+
+    let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(layout.width, layout.height, |x, y| {
+        let Rgb([r, g, b]) = *image.get_pixel(x as u32, y as u32);
+        ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8
+    });
+    let decoded = prepared.detect_grids().first().and_then(|grid| grid.decode().ok());
+    match decoded {
+        Some((_, text)) if text == content => Ok(()),
+        _ => Err(Error::Unreadable(
+            "the code does not scan with the logo over it; use a smaller `logo_size` or a larger version".into(),
+        )),
+    }
+}
+
+/// Renders as grayscale when the colors allow it, and as RGBA otherwise. The
+/// resolution, if any, goes in a `pHYs` chunk.
+pub fn png(symbol: &Symbol, options: &Options) -> Result<Vec<u8>, Error> {
+    let mut layout = Layout::new(symbol, options)?;
+    layout.add_caption(options)?;
+    layout.add_frame(options)?;
+    encode_png(symbol, &layout, options)
+}
+
+fn encode_png(symbol: &Symbol, layout: &Layout, options: &Options) -> Result<Vec<u8>, Error> {
+    let palette = Palette::new(symbol, layout, options);
+    let colors = palette.colors();
+
+    let gray = colors.iter().all(Color::is_gray) && layout.logo.is_none();
+    let (color_type, data) = if gray && colors.iter().all(Color::is_opaque) {
+        (ColorType::Grayscale, rasterize(symbol, layout, palette.map(|c| Luma([c.r]))).into_raw())
+    } else {
+        let rgba = |c: Color| Rgba([c.r, c.g, c.b, c.a]);
+        (ColorType::Rgba, rasterize(symbol, layout, palette.map(rgba)).into_raw())
+    };
+
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut encoder = Encoder::new(Cursor::new(&mut bytes), layout.width as u32, layout.height as u32);
+    encoder.set_color(color_type);
+    encoder.set_depth(BitDepth::Eight);
+    if let Some(dpi) = layout.dpi {
+        let per_metre = (dpi as f64 / 0.0254).round() as u32;
+        encoder.set_pixel_dims(Some(PixelDimensions {
+            xppu: per_metre,
+            yppu: per_metre,
+            unit: Unit::Meter,
+        }));
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+
+    Ok(bytes)
+}
+
+/// Quality of JPEG output. High enough that ringing around module edges stays
+/// well below what would flip a module when scanned.
+const JPEG_QUALITY: u8 = 95;
+
+/// Renders with the `image` crate, in the smallest color type the format and
+/// the colors allow. Formats without an alpha channel get the colors
+/// composited over white, and GIF, which only has fully transparent pixels,
+/// does too when either color is translucent.
+pub fn raster(symbol: &Symbol, options: &Options, format: ImageFormat) -> Result<Vec<u8>, Error> {
+    let mut layout = Layout::new(symbol, options)?;
+    layout.add_caption(options)?;
+    layout.add_frame(options)?;
+    let mut palette = Palette::new(symbol, &layout, options);
+
+    let translucent = |c: &Color| c.a != 0 && c.a != 255;
+    if format == ImageFormat::Jpeg || (format == ImageFormat::Gif && palette.colors().iter().any(translucent)) {
+        palette = palette.flatten();
+    }
+
+    let colors = palette.colors();
+    let (color_type, data) = if colors.iter().all(Color::is_opaque) {
+        if colors.iter().all(Color::is_gray) && layout.logo.is_none() && format != ImageFormat::Gif {
+            (ExtendedColorType::L8, rasterize(symbol, &layout, palette.map(|c| Luma([c.r]))).into_raw())
+        } else {
+            let rgb = |c: Color| Rgb([c.r, c.g, c.b]);
+            (ExtendedColorType::Rgb8, rasterize(symbol, &layout, palette.map(rgb)).into_raw())
+        }
+    } else {
+        let rgba = |c: Color| Rgba([c.r, c.g, c.b, c.a]);
+        (ExtendedColorType::Rgba8, rasterize(symbol, &layout, palette.map(rgba)).into_raw())
+    };
+
+    let (width, height) = (layout.width as u32, layout.height as u32);
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut cursor = Cursor::new(&mut bytes);
+
+    if format == ImageFormat::Jpeg {
+        let mut encoder = JpegEncoder::new_with_quality(&mut cursor, JPEG_QUALITY);
+        if let Some(dpi) = layout.dpi {
+            encoder.set_pixel_density(PixelDensity::dpi(dpi as u16));
+        }
+        encoder.encode(&data, width, height, color_type)?;
+    } else {
+        image::write_buffer_with_format(&mut cursor, &data, width, height, color_type, format)?;
+    }
+
+    Ok(bytes)
+}
+
+/// SVG `fill` attributes for `color`.
+fn fill(color: Color) -> String {
+    match color.is_opaque() {
+        true => format!(r#"fill="{}""#, color.hex()),
+        false => format!(r#"fill="{}" fill-opacity="{:.3}""#, color.hex(), color.opacity()),
+    }
+}
+
+/// SVG `fill` attributes for `paint`. Gradients refer to the definition
+/// written by `gradient_definition`.
+fn paint_fill(paint: &Paint<Color>) -> String {
+    match paint {
+        Paint::Solid(color) => fill(*color),
+        Paint::Gradient(..) => r#"fill="url(#gradient)""#.into(),
+    }
+}
+
+/// SVG definition of the gradient of `paint`, if it has one. It is laid out
+/// in the same pixels as the raster output, so that both look the same.
+fn gradient_definition(paint: &Paint<Color>) -> Option<String> {
+    let Paint::Gradient(spread, stops) = paint else {
+        return None;
+    };
+
+    let n = pdf::number;
+    let mut defs = match *spread {
+        Spread::Linear((x1, y1), (x2, y2)) => format!(
+            r#"<defs><linearGradient id="gradient" gradientUnits="userSpaceOnUse" x1="{}" y1="{}" x2="{}" y2="{}">"#,
+            n(x1),
+            n(y1),
+            n(x2),
+            n(y2)
+        ),
+        Spread::Radial((cx, cy), r) => format!(
+            r#"<defs><radialGradient id="gradient" gradientUnits="userSpaceOnUse" cx="{}" cy="{}" r="{}">"#,
+            n(cx),
+            n(cy),
+            n(r)
+        ),
+    };
+    for &(position, color) in stops {
+        write!(defs, r#"<stop offset="{}" stop-color="{}""#, n(position), color.hex()).unwrap();
+        if !color.is_opaque() {
+            write!(defs, r#" stop-opacity="{:.3}""#, color.opacity()).unwrap();
+        }
+        defs.push_str("/>");
+    }
+    defs.push_str(match spread {
+        Spread::Linear(..) => "</linearGradient></defs>",
+        Spread::Radial(..) => "</radialGradient></defs>",
+    });
+    Some(defs)
+}
+
+/// Renders as SVG. Captions are `<text>` that is stretched to the width
+/// measured with the embedded font, so that lines fit whatever font the
+/// viewer substitutes, and also give the image its accessible name. The text
+/// of frames is set the same way.
+pub fn svg(symbol: &Symbol, options: &Options) -> Result<String, Error> {
+    let mut layout = Layout::new(symbol, options)?;
+    layout.add_caption(options)?;
+    layout.add_frame(options)?;
+    let (w, h, size) = (layout.width, layout.height, layout.module_size);
+
+    // With a resolution, the image prints at the same size as the PNG would.
+    let (width, height) = match layout.dpi {
+        Some(dpi) => {
+            let mm = |pixels: usize| format!("{:.3}mm", pixels as f64 * 25.4 / dpi as f64);
+            (mm(w), mm(h))
+        }
+        None => (w.to_string(), h.to_string()),
+    };
+
+    let mut svg = format!(
+        concat!(
+            r#"<?xml version="1.0" standalone="yes"?>"#,
+            r#"<svg xmlns="http://www.w3.org/2000/svg"{xlink}"#,
+            r#" version="1.1" width="{width}" height="{height}""#,
+            r#" viewBox="0 0 {w} {h}"{rendering}{accessibility}>"#,
+        ),
+        width = width,
+        height = height,
+        w = w,
+        h = h,
+        // Only squares line up with pixels; other shapes need antialiasing.
+        rendering = match (options.style, options.eye_frame, options.eye_pupil, options.frame) {
+            (
+                ModuleStyle::Square,
+                None | Some(EyeShape::Square),
+                None | Some(EyeShape::Square),
+                None | Some(FrameKind::Banner),
+            ) => r#" shape-rendering="crispEdges""#,
+            _ => "",
+        },
+        xlink = match layout.logo {
+            Some(_) => r#" xmlns:xlink="http://www.w3.org/1999/xlink""#,
+            None => "",
+        },
+        accessibility = match options.caption {
+            Some(_) => r#" role="img" aria-labelledby="caption""#,
+            None => "",
+        },
+    );
+
+    if let Some(text) = &options.caption {
+        write!(svg, r#"<title id="caption">{}</title>"#, escape_html(text)).unwrap();
+    }
+
+    let palette = Palette::new(symbol, &layout, options);
+    if let Some(defs) = gradient_definition(&palette.fg) {
+        svg.push_str(&defs);
+    }
+
+    if options.bg.a > 0 {
+        write!(svg, r#"<rect x="0" y="0" width="{w}" height="{h}" {}/>"#, fill(options.bg)).unwrap();
+    }
+
+    if let (Some(frame), Some((color, _))) = (&layout.frame, palette.frame) {
+        write!(svg, r#"<path {} fill-rule="evenodd" d=""#, fill(color)).unwrap();
+        frame.path(&mut svg, Outline::svg_path);
+        svg.push_str(r#""/>"#);
+    }
+
+    write!(svg, r#"<path {} d=""#, paint_fill(&palette.fg)).unwrap();
+
+    for (left, top, shape) in layout.dark_modules(symbol) {
+        shape.path(&mut svg, left, top, size);
+    }
+
+    svg.push_str(r#""/>"#);
+
+    if !layout.eyes.is_empty() {
+        write!(svg, r#"<path {} fill-rule="evenodd" d=""#, paint_fill(&palette.ring)).unwrap();
+        for &(x, y, eye) in &layout.eyes {
+            let (left, top) = layout.pixel(x, y);
+            eye.ring_path(&mut svg, left, top, size);
+        }
+        write!(svg, r#""/><path {} d=""#, paint_fill(&palette.pupil)).unwrap();
+        for &(x, y, eye) in &layout.eyes {
+            let (left, top) = layout.pixel(x, y);
+            eye.pupil_path(&mut svg, left, top, size);
+        }
+        svg.push_str(r#""/>"#);
+    }
+
+    if let (Some(logo), Some((left, top, size))) = (&layout.logo, layout.logo_box()) {
+        write!(
+            svg,
+            r#"<image x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="xMidYMid meet" xlink:href="{}"/>"#,
+            left,
+            top,
+            size,
+            size,
+            embed::data_uri_of(logo.content_type, &logo.data)
+        )
+        .unwrap();
+    }
+
+    if let Some(caption) = &layout.caption {
+        for (line, line_width, left, baseline) in caption.positions(w) {
+            write!(
+                svg,
+                concat!(
+                    r#"<text x="{:.2}" y="{:.2}" font-family="{}" font-size="{:.2}" textLength="{:.2}""#,
+                    r#" lengthAdjust="spacingAndGlyphs" {} aria-hidden="true">{}</text>"#,
+                ),
+                left,
+                baseline,
+                caption::FONT_FAMILY,
+                caption.size,
+                line_width,
+                paint_fill(&palette.fg),
+                escape_html(line)
+            )
+            .unwrap();
+        }
+    }
+
+    if let (Some(frame), Some((_, color))) = (&layout.frame, palette.frame) {
+        write!(
+            svg,
+            concat!(
+                r#"<text x="{:.2}" y="{:.2}" font-family="{}" font-size="{:.2}" textLength="{:.2}""#,
+                r#" lengthAdjust="spacingAndGlyphs" {}>{}</text>"#,
+            ),
+            frame.text_left,
+            frame.baseline,
+            caption::FONT_FAMILY,
+            frame.text_size,
+            frame.text_width,
+            fill(color),
+            escape_html(&frame.text)
+        )
+        .unwrap();
+    }
+
+    svg.push_str("</svg>");
+    Ok(svg)
+}
+
+/// PDF and PostScript operands setting `color`, followed by `operator`.
+fn color_operands(color: Color, operator: &str) -> String {
+    let c = |v: u8| pdf::number(v as f64 / 255.0);
+    format!("{} {} {} {}", c(color.r), c(color.g), c(color.b), operator)
+}
+
+/// Renders on a single page, either fitting the symbol or with the symbol
+/// centred on a paper size. Translucent colors use graphics states.
+pub fn pdf(symbol: &Symbol, options: &Options) -> Result<Vec<u8>, Error> {
+    let mut layout = Layout::new(symbol, options)?;
+    layout.add_frame(options)?;
+    let point = layout.point_size(options);
+    let (width, height) = (layout.width as f64 * point, layout.height as f64 * point);
+
+    let (page_width, page_height) = match options.page.size() {
+        Some((page_width, page_height)) if width > page_width || height > page_height => {
+            return Err(Error::Size(format!(
+                "symbol is {:.1}mm by {:.1}mm, which does not fit on the {} page",
+                width / 72.0 * 25.4,
+                height / 72.0 * 25.4,
+                options.page.name()
+            )))
+        }
+        Some(page) => page,
+        None => (width, height),
+    };
+
+    // Draw in pixels from the top-left corner, as for the other formats.
+    let mut content = format!(
+        "{} 0 0 {} {} {} cm\n",
+        pdf::number(point),
+        pdf::number(-point),
+        pdf::number((page_width - width) / 2.0),
+        pdf::number((page_height + height) / 2.0)
+    );
+
+    let frame_colors = Palette::new(symbol, &layout, options).frame;
+    let mut states = String::new();
+    let frame_states = frame_colors.map(|(frame, text)| [("Fr", frame), ("Tx", text)]).into_iter().flatten();
+    for (name, color) in [("Bg", options.bg), ("Fg", options.fg)].into_iter().chain(frame_states) {
+        if !color.is_opaque() {
+            write!(states, "/{} << /ca {} >> ", name, pdf::number(color.opacity())).unwrap();
+        }
+    }
+
+    // The background's graphics state is saved and restored around it, so
+    // that its opacity does not carry over to the modules.
+    if options.bg.a > 0 {
+        content.push_str("q ");
+        if !options.bg.is_opaque() {
+            content.push_str("/Bg gs ");
+        }
+        writeln!(content, "{} 0 0 {} {} re f Q", color_operands(options.bg, "rg"), layout.width, layout.height).unwrap();
+    }
+
+    // The frame's holes leave the background showing, and its text is drawn
+    // from the outlines of the embedded font, as in the other formats.
+    if let (Some(frame), Some((frame_color, text_color))) = (&layout.frame, frame_colors) {
+        content.push_str("q ");
+        if !frame_color.is_opaque() {
+            content.push_str("/Fr gs ");
+        }
+        writeln!(content, "{}", color_operands(frame_color, "rg")).unwrap();
+        frame.path(&mut content, Outline::pdf_path);
+        content.push_str("f* Q q ");
+        if !text_color.is_opaque() {
+            content.push_str("/Tx gs ");
+        }
+        writeln!(content, "{}", color_operands(text_color, "rg")).unwrap();
+        frame.text_pdf_path(&mut content);
+        content.push_str("f Q\n");
+    }
+
+    if !options.fg.is_opaque() {
+        content.push_str("/Fg gs ");
+    }
+    writeln!(content, "{}", color_operands(options.fg, "rg")).unwrap();
+    for (left, top, run) in layout.dark_runs(symbol) {
+        writeln!(content, "{} {} {} {} re", left, top, run, layout.module_size).unwrap();
+    }
+    content.push_str("f\n");
+
+    let resources = match states.is_empty() {
+        true => String::new(),
+        false => format!("/ExtGState << {}>>", states),
+    };
+
+    let mut document = Document::new();
+    document.add_page(page_width, page_height, &resources, &content);
+    Ok(document.finish())
+}
+
+/// Draws the symbol and its quiet zone as PDF operators, as large as fits in
+/// a box of `width` by `height` points whose top-left corner is at `x`, `y`,
+/// and centred in it. Colors are flattened as for EPS, since this is for
+/// sheets that go to a printer.
+pub fn pdf_symbol(
+    content: &mut String,
+    symbol: &Symbol,
+    options: &Options,
+    (x, y): (f64, f64),
+    (width, height): (f64, f64),
+) {
+    let quiet_zone = quiet_zone(symbol, options);
+    let layout = Layout {
+        dpi: None,
+        quiet_zone,
+        module_size: 1,
+        width: symbol.width + 2 * quiet_zone,
+        height: symbol.height + 2 * quiet_zone,
+        left: 0,
+        top: 0,
+        caption: None,
+        frame: None,
+        clear: None,
+        logo: None,
+        style: ModuleStyle::Square,
+        style_finders: false,
+        eyes: Vec::new(),
+    };
+    let module = (width / layout.width as f64).min(height / layout.height as f64);
+    let left = x + (width - layout.width as f64 * module) / 2.0;
+    let top = y - (height - layout.height as f64 * module) / 2.0;
+
+    let (fg, bg) = color::flatten_opaque_fg(options.fg, options.bg);
+
+    writeln!(
+        content,
+        "q {} 0 0 {} {} {} cm",
+        pdf::number(module),
+        pdf::number(-module),
+        pdf::number(left),
+        pdf::number(top)
+    )
+    .unwrap();
+    if bg.a > 0 {
+        writeln!(content, "{} 0 0 {} {} re f", color_operands(bg, "rg"), layout.width, layout.height).unwrap();
+    }
+    writeln!(content, "{}", color_operands(fg, "rg")).unwrap();
+    for (left, top, run) in layout.dark_runs(symbol) {
+        writeln!(content, "{} {} {} 1 re", left, top, run).unwrap();
+    }
+    content.push_str("f Q\n");
+}
+
+/// Renders as Encapsulated PostScript, sized like the PDF output. PostScript
+/// has no transparency, so translucent colors are composited over white, and
+/// a fully transparent background is simply left unpainted.
+pub fn eps(symbol: &Symbol, options: &Options) -> Result<Vec<u8>, Error> {
+    let layout = Layout::new(symbol, options)?;
+    let point = layout.point_size(options);
+    let (width, height) = (layout.width as f64 * point, layout.height as f64 * point);
+
+    let (fg, bg) = color::flatten_opaque_fg(options.fg, options.bg);
+
+    let mut eps = format!(
+        concat!(
+            "%!PS-Adobe-3.0 EPSF-3.0\n",
+            "%%BoundingBox: 0 0 {} {}\n",
+            "%%HiResBoundingBox: 0 0 {} {}\n",
+            "%%Creator: qqr\n",
+            "%%LanguageLevel: 2\n",
+            "%%EndComments\n",
+            "gsave\n",
+            // Draw in pixels from the top-left corner, as for the other formats.
+            "0 {} translate {} {} scale\n",
+        ),
+        width.ceil(),
+        height.ceil(),
+        pdf::number(width),
+        pdf::number(height),
+        pdf::number(height),
+        pdf::number(point),
+        pdf::number(-point),
+    );
+
+    if bg.a > 0 {
+        writeln!(eps, "{} 0 0 {} {} rectfill", color_operands(bg, "setrgbcolor"), layout.width, layout.height).unwrap();
+    }
+
+    writeln!(eps, "{}", color_operands(fg, "setrgbcolor")).unwrap();
+    for (left, top, run) in layout.dark_runs(symbol) {
+        writeln!(eps, "{} {} {} {} rectfill", left, top, run, layout.module_size).unwrap();
+    }
+
+    eps.push_str("grestore\n%%EOF\n");
+    Ok(eps.into_bytes())
+}
+
+/// Character-based renderings, for terminals.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum TextStyle {
+    /// Half-block characters, two rows of modules per line.
+    Unicode,
+    /// `##` for each dark module and two spaces for each light one.
+    ASCII,
+    /// Upper half blocks colored with `fg` and `bg` as 24-bit ANSI colors,
+    /// two rows of modules per line.
+    ANSI,
+}
+
+/// Renders as text. Characters stand for dark modules, which reads correctly
+/// on light terminals; `invert` swaps them for dark terminals. The ANSI style
+/// paints both colors itself, and `invert` swaps those instead.
+pub fn text(symbol: &Symbol, options: &Options, style: TextStyle) -> String {
+    let quiet_zone = quiet_zone(symbol, options) as isize;
+    let (width, height) = (symbol.width as isize, symbol.height as isize);
+
+    // Modules in the quiet zone, and the empty row below odd heights, are light.
+    let dark = |x: isize, y: isize| {
+        (0..width).contains(&x) && (0..height).contains(&y) && symbol.is_dark(x as usize, y as usize)
+    };
+    let ink = |x: isize, y: isize| dark(x, y) != options.invert;
+
+    let (fg, bg) = color::flatten(options.fg, options.bg);
+    let (fg, bg) = match options.invert {
+        true => (bg, fg),
+        false => (fg, bg),
+    };
+
+    let xs = -quiet_zone..width + quiet_zone;
+    let ys = -quiet_zone..height + quiet_zone;
+    let mut text = String::new();
+
+    match style {
+        TextStyle::ASCII => {
+            for y in ys {
+                for x in xs.clone() {
+                    text.push_str(if ink(x, y) { "##" } else { "  " });
+                }
+                text.push('\n');
+            }
+        }
+        TextStyle::Unicode => {
+            for y in ys.step_by(2) {
+                for x in xs.clone() {
+                    text.push(match (ink(x, y), ink(x, y + 1)) {
+                        (true, true) => '\u{2588}',
+                        (true, false) => '\u{2580}',
+                        (false, true) => '\u{2584}',
+                        (false, false) => ' ',
+                    });
+                }
+                text.push('\n');
+            }
+        }
+        TextStyle::ANSI => {
+            for y in ys.step_by(2) {
+                // Colors are only set when they change from the previous cell.
+                let mut last = None;
+                for x in xs.clone() {
+                    let color = |y: isize| if dark(x, y) { fg } else { bg };
+                    let (upper, lower) = (color(y), color(y + 1));
+                    if last != Some((upper, lower)) {
+                        write!(
+                            text,
+                            "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                            upper.r, upper.g, upper.b, lower.r, lower.g, lower.b
+                        )
+                        .unwrap();
+                        last = Some((upper, lower));
+                    }
+                    text.push('\u{2580}');
+                }
+                text.push_str("\x1b[0m\n");
+            }
+        }
+    }
+
+    text
+}
+
+/// Layout for terminal graphics: by default two modules per cell vertically,
+/// like the Unicode text rendering, and padded to a whole number of cells.
+fn terminal_layout(symbol: &Symbol, options: &Options) -> Result<Layout, Error> {
+    let mut layout = Layout::with_default_scale(symbol, options, Some((options.cell.1 / 2).max(1)))?;
+    layout.pad_to(options.cell);
+    Ok(layout)
+}
+
+/// Renders as a sixel image. Light pixels are left unpainted when `bg` is
+/// transparent, and translucent colors are composited otherwise.
+pub fn sixel(symbol: &Symbol, options: &Options) -> Result<String, Error> {
+    let layout = terminal_layout(symbol, options)?;
+    let (fg, bg) = color::flatten_opaque_fg(options.fg, options.bg);
+
+    // Color registers: 0 for light pixels and 1 for dark ones.
+    let (dark, light) = (Luma([1u8]), Luma([0u8]));
+    let image = rasterize(
+        symbol,
+        &layout,
+        Palette {
+            fg: Paint::Solid(dark),
+            bg: light,
+            ring: Paint::Solid(dark),
+            pupil: Paint::Solid(dark),
+            frame: None,
+        },
+    );
+    let register = |i: u8, c: Color| {
+        let percent = |v: u8| v as u32 * 100 / 255;
+        format!("#{};2;{};{};{}", i, percent(c.r), percent(c.g), percent(c.b))
+    };
+
+    // Pixels that no color sets keep the terminal's background.
+    let mut sixel = format!(
+        "\x1bP0;1;0q\"1;1;{};{}{}{}",
+        layout.width,
+        layout.height,
+        register(0, bg),
+        register(1, fg)
+    );
+
+    let registers: &[u8] = match bg.a {
+        0 => &[1],
+        _ => &[0, 1],
+    };
+
+    for band in (0..layout.height).step_by(6) {
+        for (i, &register) in registers.iter().enumerate() {
+            if i > 0 {
+                sixel.push('$');
+            }
+            write!(sixel, "#{}", register).unwrap();
+
+            // Each character holds a column of six pixels, run-length encoded.
+            let column = |x: usize| {
+                (0..6)
+                    .filter(|dy| band + dy < layout.height && image.get_pixel(x as u32, (band + dy) as u32)[0] == register)
+                    .fold(0u8, |bits, dy| bits | 1 << dy)
+            };
+            let mut x = 0;
+            while x < layout.width {
+                let bits = column(x);
+                let start = x;
+                while x < layout.width && column(x) == bits {
+                    x += 1;
+                }
+                let c = (63 + bits) as char;
+                match x - start {
+                    1..=3 => (start..x).for_each(|_| sixel.push(c)),
+                    run => write!(sixel, "!{}{}", run, c).unwrap(),
+                }
+            }
+        }
+        sixel.push('-');
+    }
+
+    sixel.push_str("\x1b\\\n");
+    Ok(sixel)
+}
+
+/// Largest chunk of base64 data per escape sequence in the Kitty graphics
+/// protocol.
+const KITTY_CHUNK: usize = 4096;
+
+/// Renders as a PNG transmitted with the Kitty graphics protocol, displayed
+/// over exactly the cells the image was padded to.
+pub fn kitty(symbol: &Symbol, options: &Options) -> Result<String, Error> {
+    let layout = terminal_layout(symbol, options)?;
+    let data = BASE64_STANDARD.encode(encode_png(symbol, &layout, options)?);
+    let (columns, rows) = (layout.width / options.cell.0, layout.height / options.cell.1);
+
+    let chunks: Vec<_> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut kitty = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        match i {
+            0 => write!(kitty, "\x1b_Ga=T,f=100,q=2,c={},r={},m={};", columns, rows, more).unwrap(),
+            _ => write!(kitty, "\x1b_Gm={};", more).unwrap(),
+        }
+        kitty.push_str(std::str::from_utf8(chunk).unwrap());
+        kitty.push_str("\x1b\\");
+    }
+
+    kitty.push('\n');
+    Ok(kitty)
+}
+
+/// The module matrix, either as rows of booleans or bit-packed.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Modules {
+    Rows(Vec<Vec<bool>>),
+    /// Base64 of the modules in row-major order, eight per byte from the most
+    /// significant bit, with the last byte padded with zeros.
+    Packed(String),
+}
+
+#[derive(Serialize)]
+struct Matrix {
+    symbol: &'static str,
+    version: i16,
+    version_name: String,
+    ec: String,
+    mask: Option<u8>,
+    width: usize,
+    height: usize,
+    quiet_zone: usize,
+    modules: Modules,
+    /// Indexes into `function_names` for every module, row by row.
+    functions: Vec<Vec<u8>>,
+    function_names: Vec<&'static str>,
+}
+
+/// Describes the symbol as JSON, for clients that draw it themselves.
+pub fn json(symbol: &Symbol, options: &Options) -> String {
+    let rows = (0..symbol.height).map(|y| (0..symbol.width).map(move |x| (x, y)));
+
+    let modules = match options.packed {
+        true => {
+            let mut bytes = vec![0u8; (symbol.width * symbol.height).div_ceil(8)];
+            for (i, (x, y)) in rows.clone().flatten().enumerate() {
+                if symbol.is_dark(x, y) {
+                    bytes[i / 8] |= 0x80 >> (i % 8);
+                }
+            }
+            Modules::Packed(BASE64_STANDARD.encode(bytes))
+        }
+        false => Modules::Rows(rows.clone().map(|row| row.map(|(x, y)| symbol.is_dark(x, y)).collect()).collect()),
+    };
+
+    let functions = rows
+        .map(|row| {
+            row.map(|(x, y)| Function::ALL.iter().position(|&f| f == symbol.function(x, y)).unwrap() as u8)
+                .collect()
+        })
+        .collect();
+
+    let matrix = Matrix {
+        symbol: symbol.kind.name(),
+        version: symbol.version,
+        version_name: symbol.kind.version_name(symbol.version),
+        ec: format!("{:?}", symbol.ec),
+        mask: symbol.mask,
+        width: symbol.width,
+        height: symbol.height,
+        quiet_zone: quiet_zone(symbol, options),
+        modules,
+        functions,
+        function_names: Function::ALL.iter().map(|f| f.name()).collect(),
+    };
+
+    serde_json::to_string(&matrix).unwrap()
+}
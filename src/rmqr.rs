@@ -0,0 +1,422 @@
+//! Rectangular Micro QR (rMQR) encoder, as specified by ISO/IEC 23941.
+//!
+//! The `qrcode` crate only covers QR and Micro QR, so rMQR symbols are built
+//! here. Content is encoded as a single numeric, alphanumeric or byte segment,
+//! and rMQR only has the one mask pattern, so there is nothing to optimize.
+
+use qrcode::ec::create_error_correction_code;
+use qrcode::EcLevel;
+
+use crate::symbol::{Function, Symbol, SymbolKind};
+
+struct Blocks {
+    /// Error correction codewords in each block.
+    ec: usize,
+    /// Pairs of (number of blocks, data codewords per block).
+    groups: [(usize, usize); 2],
+}
+
+struct RmqrVersion {
+    height: usize,
+    width: usize,
+    /// Columns of the alignment patterns and vertical timing patterns.
+    alignment: &'static [usize],
+    m: Blocks,
+    h: Blocks,
+}
+
+impl RmqrVersion {
+    fn blocks(&self, ec: EcLevel) -> &Blocks {
+        match ec {
+            EcLevel::H => &self.h,
+            _ => &self.m,
+        }
+    }
+
+    fn data_codewords(&self, ec: EcLevel) -> usize {
+        self.blocks(ec).groups.iter().map(|(count, data)| count * data).sum()
+    }
+}
+
+macro_rules! version {
+    ($h:expr, $w:expr, $align:expr, ($m:expr, $m1:expr, $m2:expr), ($e:expr, $h1:expr, $h2:expr)) => {
+        RmqrVersion {
+            height: $h,
+            width: $w,
+            alignment: &$align,
+            m: Blocks { ec: $m, groups: [$m1, $m2] },
+            h: Blocks { ec: $e, groups: [$h1, $h2] },
+        }
+    };
+}
+
+/// ISO/IEC 23941 tables 6 and 8, in version order.
+const VERSIONS: [RmqrVersion; 32] = [
+    version!(7, 43, [21], (7, (1, 6), (0, 0)), (10, (1, 3), (0, 0))),
+    version!(7, 59, [19, 39], (9, (1, 12), (0, 0)), (14, (1, 7), (0, 0))),
+    version!(7, 77, [25, 51], (12, (1, 20), (0, 0)), (22, (1, 10), (0, 0))),
+    version!(7, 99, [23, 49, 75], (16, (1, 28), (0, 0)), (30, (1, 14), (0, 0))),
+    version!(7, 139, [27, 55, 83, 111], (24, (1, 44), (0, 0)), (22, (2, 12), (0, 0))),
+    version!(9, 43, [21], (9, (1, 12), (0, 0)), (14, (1, 7), (0, 0))),
+    version!(9, 59, [19, 39], (12, (1, 21), (0, 0)), (22, (1, 11), (0, 0))),
+    version!(9, 77, [25, 51], (18, (1, 31), (0, 0)), (16, (1, 8), (1, 9))),
+    version!(9, 99, [23, 49, 75], (24, (1, 42), (0, 0)), (22, (2, 11), (0, 0))),
+    version!(9, 139, [27, 55, 83, 111], (18, (1, 31), (1, 32)), (22, (3, 11), (0, 0))),
+    version!(11, 27, [], (8, (1, 7), (0, 0)), (10, (1, 5), (0, 0))),
+    version!(11, 43, [21], (12, (1, 19), (0, 0)), (20, (1, 11), (0, 0))),
+    version!(11, 59, [19, 39], (16, (1, 31), (0, 0)), (16, (1, 7), (1, 8))),
+    version!(11, 77, [25, 51], (24, (1, 43), (0, 0)), (22, (1, 11), (1, 12))),
+    version!(11, 99, [23, 49, 75], (16, (1, 28), (1, 29)), (30, (1, 14), (1, 15))),
+    version!(11, 139, [27, 55, 83, 111], (24, (2, 42), (0, 0)), (30, (3, 14), (0, 0))),
+    version!(13, 27, [], (9, (1, 12), (0, 0)), (14, (1, 7), (0, 0))),
+    version!(13, 43, [21], (14, (1, 27), (0, 0)), (28, (1, 13), (0, 0))),
+    version!(13, 59, [19, 39], (22, (1, 38), (0, 0)), (20, (2, 10), (0, 0))),
+    version!(13, 77, [25, 51], (16, (1, 26), (1, 27)), (28, (1, 14), (1, 15))),
+    version!(13, 99, [23, 49, 75], (20, (1, 36), (1, 37)), (26, (1, 11), (2, 12))),
+    version!(13, 139, [27, 55, 83, 111], (20, (2, 35), (1, 36)), (28, (2, 13), (2, 14))),
+    version!(15, 43, [21], (18, (1, 33), (0, 0)), (18, (1, 7), (1, 8))),
+    version!(15, 59, [19, 39], (26, (1, 48), (0, 0)), (24, (2, 13), (0, 0))),
+    version!(15, 77, [25, 51], (18, (1, 33), (1, 34)), (24, (2, 10), (1, 11))),
+    version!(15, 99, [23, 49, 75], (24, (2, 44), (0, 0)), (22, (4, 12), (0, 0))),
+    version!(15, 139, [27, 55, 83, 111], (24, (2, 42), (1, 43)), (26, (1, 13), (4, 14))),
+    version!(17, 43, [21], (22, (1, 39), (0, 0)), (20, (1, 10), (1, 11))),
+    version!(17, 59, [19, 39], (16, (2, 28), (0, 0)), (30, (2, 14), (0, 0))),
+    version!(17, 77, [25, 51], (22, (2, 39), (0, 0)), (28, (1, 12), (2, 13))),
+    version!(17, 99, [23, 49, 75], (20, (2, 33), (1, 34)), (26, (4, 14), (0, 0))),
+    version!(17, 139, [27, 55, 83, 111], (20, (4, 38), (0, 0)), (26, (2, 12), (4, 13))),
+];
+
+/// Character count indicator lengths (ISO/IEC 23941 table 3), indexed by
+/// [`Mode`] and version.
+const COUNT_BITS: [[usize; 32]; 3] = [
+    [
+        4, 5, 6, 7, 7, 5, 6, 7, 7, 8, 4, 6, 7, 7, 8, 8, 5, 6, 7, 7, 8, 8, 7, 7, 8, 8, 9, 7, 8, 8, 8, 9,
+    ],
+    [
+        3, 5, 5, 6, 6, 5, 5, 6, 6, 7, 4, 5, 6, 6, 7, 7, 5, 6, 6, 7, 7, 8, 6, 7, 7, 7, 8, 6, 7, 7, 8, 8,
+    ],
+    [
+        3, 4, 5, 5, 6, 4, 5, 5, 6, 6, 3, 5, 5, 6, 6, 7, 4, 5, 6, 6, 7, 7, 6, 6, 7, 7, 7, 6, 6, 7, 7, 8,
+    ],
+];
+
+const ALPHANUMERIC: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Format information masks for the finder pattern and finder sub-pattern
+/// sides.
+const FORMAT_MASKS: [u32; 2] = [0x1FAB2, 0x20A7B];
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Numeric = 0,
+    Alphanumeric = 1,
+    Byte = 2,
+}
+
+impl Mode {
+    fn of(data: &[u8]) -> Mode {
+        if data.iter().all(u8::is_ascii_digit) {
+            Mode::Numeric
+        } else if data.iter().all(|c| ALPHANUMERIC.contains(c)) {
+            Mode::Alphanumeric
+        } else {
+            Mode::Byte
+        }
+    }
+
+    fn indicator(self) -> u32 {
+        self as u32 + 1
+    }
+
+    fn data_bits(self, len: usize) -> usize {
+        match self {
+            Mode::Numeric => len / 3 * 10 + [0, 4, 7][len % 3],
+            Mode::Alphanumeric => len / 2 * 11 + (len % 2) * 6,
+            Mode::Byte => len * 8,
+        }
+    }
+}
+
+/// Returns the `(height, width)` of `version`, numbered from 1.
+pub fn size(version: i16) -> (usize, usize) {
+    let version = &VERSIONS[version as usize - 1];
+    (version.height, version.width)
+}
+
+/// Number of data bits `version` holds at `ec`, and how many bytes of binary
+/// content that amounts to.
+pub fn capacity(version: i16, ec: EcLevel) -> (usize, usize) {
+    let index = version as usize - 1;
+    let bits = VERSIONS[index].data_codewords(ec) * 8;
+    (bits, bits.saturating_sub(3 + COUNT_BITS[Mode::Byte as usize][index]) / 8)
+}
+
+/// Encodes `data` in the smallest (by area) version between `min` and `max`
+/// that can hold it. Returns `None` if none can.
+pub fn encode(data: &[u8], ec: EcLevel, min: i16, max: i16) -> Option<Symbol> {
+    let mode = Mode::of(data);
+
+    let mut candidates: Vec<usize> = (min as usize - 1..max as usize)
+        .filter(|&i| {
+            let count_bits = COUNT_BITS[mode as usize][i];
+            data.len() < 1 << count_bits
+                && 3 + count_bits + mode.data_bits(data.len()) <= VERSIONS[i].data_codewords(ec) * 8
+        })
+        .collect();
+    candidates.sort_by_key(|&i| VERSIONS[i].width * VERSIONS[i].height);
+
+    let index = *candidates.first()?;
+    Some(build(index, ec, &codewords(index, ec, mode, data)))
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitWriter {
+    fn push(&mut self, value: u32, bits: usize) {
+        for i in (0..bits).rev() {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if value >> i & 1 == 1 {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+}
+
+/// Builds the final codeword sequence: data codewords with padding, followed
+/// by error correction codewords, both interleaved across blocks.
+fn codewords(index: usize, ec: EcLevel, mode: Mode, data: &[u8]) -> Vec<u8> {
+    let version = &VERSIONS[index];
+    let capacity = version.data_codewords(ec);
+
+    let mut bits = BitWriter { bytes: Vec::new(), len: 0 };
+    bits.push(mode.indicator(), 3);
+    bits.push(data.len() as u32, COUNT_BITS[mode as usize][index]);
+    match mode {
+        Mode::Numeric => {
+            for chunk in data.chunks(3) {
+                let value = chunk.iter().fold(0, |acc, c| acc * 10 + u32::from(c - b'0'));
+                bits.push(value, [0, 4, 7, 10][chunk.len()]);
+            }
+        }
+        Mode::Alphanumeric => {
+            let value = |c: &u8| ALPHANUMERIC.iter().position(|a| a == c).unwrap() as u32;
+            for chunk in data.chunks(2) {
+                match chunk {
+                    [a, b] => bits.push(value(a) * 45 + value(b), 11),
+                    [a] => bits.push(value(a), 6),
+                    _ => unreachable!(),
+                }
+            }
+        }
+        Mode::Byte => {
+            for &byte in data {
+                bits.push(byte.into(), 8);
+            }
+        }
+    }
+
+    // The terminator is truncated when the symbol is full.
+    bits.push(0, 3.min(capacity * 8 - bits.len));
+    let mut bytes = bits.bytes;
+    for pad in [0xEC, 0x11].into_iter().cycle().take(capacity - bytes.len()) {
+        bytes.push(pad);
+    }
+
+    let blocks = version.blocks(ec);
+    let mut data_blocks = Vec::new();
+    let mut rest = &bytes[..];
+    for &(count, size) in &blocks.groups {
+        for _ in 0..count {
+            let (block, tail) = rest.split_at(size);
+            data_blocks.push(block);
+            rest = tail;
+        }
+    }
+    let ec_blocks: Vec<Vec<u8>> = data_blocks.iter().map(|b| create_error_correction_code(b, blocks.ec)).collect();
+
+    let longest = data_blocks.iter().map(|b| b.len()).max().unwrap_or(0);
+    let mut result = Vec::new();
+    for i in 0..longest {
+        result.extend(data_blocks.iter().filter_map(|b| b.get(i)));
+    }
+    for i in 0..blocks.ec {
+        result.extend(ec_blocks.iter().map(|b| b[i]));
+    }
+    result
+}
+
+/// Lays out the function patterns, then places the codewords in the remaining
+/// modules in two-module wide columns, from the right, snaking up and down.
+fn build(index: usize, ec: EcLevel, codewords: &[u8]) -> Symbol {
+    let version = &VERSIONS[index];
+    let (w, h) = (version.width, version.height);
+    let mut modules = vec![false; w * h];
+    let mut functions = vec![Function::Data; w * h];
+
+    let mut set = |x: usize, y: usize, dark: bool, function: Function| {
+        modules[y * w + x] = dark;
+        functions[y * w + x] = function;
+    };
+
+    // Timing patterns along the edges.
+    for x in 0..w {
+        set(x, 0, x % 2 == 0, Function::Timing);
+        set(x, h - 1, x % 2 == 0, Function::Timing);
+    }
+    for y in 0..h {
+        set(0, y, y % 2 == 0, Function::Timing);
+        set(w - 1, y, y % 2 == 0, Function::Timing);
+    }
+
+    // Alignment patterns, and the vertical timing patterns between them.
+    for &cx in version.alignment {
+        for (dx, dy) in (0..3).flat_map(|dx| (0..3).map(move |dy| (dx, dy))) {
+            let dark = dx != 1 || dy != 1;
+            set(cx + dx - 1, dy, dark, Function::Alignment);
+            set(cx + dx - 1, h - 3 + dy, dark, Function::Alignment);
+        }
+        for y in 3..h - 3 {
+            set(cx, y, y % 2 == 0, Function::Timing);
+        }
+    }
+
+    // Finder pattern and its separator.
+    for y in 0..h.min(8) {
+        for x in 0..8 {
+            let ring = x.max(y).max(6 - x.min(6)).max(6 - y.min(6));
+            match x < 7 && y < 7 {
+                true => set(x, y, ring != 5, Function::Finder),
+                false => set(x, y, false, Function::Separator),
+            }
+        }
+    }
+
+    // Finder sub-pattern.
+    for y in 0..5 {
+        for x in 0..5 {
+            let ring = x.max(y).max(4 - x).max(4 - y);
+            set(w - 5 + x, h - 5 + y, ring != 3, Function::Finder);
+        }
+    }
+
+    // Corner finder patterns.
+    set(w - 3, 0, true, Function::Finder);
+    set(w - 2, 0, true, Function::Finder);
+    set(w - 2, 1, false, Function::Finder);
+    set(w - 1, 1, true, Function::Finder);
+    set(1, h - 1, true, Function::Finder);
+    set(2, h - 1, true, Function::Finder);
+    if h >= 11 {
+        set(0, h - 2, true, Function::Finder);
+        set(1, h - 2, false, Function::Finder);
+    }
+
+    // Format information, next to the finder pattern and the sub-pattern.
+    let info = (u32::from(ec == EcLevel::H) << 5) | index as u32;
+    let mut bch = info << 12;
+    for i in (12..18).rev() {
+        if bch >> i & 1 == 1 {
+            bch ^= 0b1_1111_0010_0101 << (i - 12);
+        }
+    }
+    let format = (info << 12) | bch;
+    let [main, sub] = FORMAT_MASKS.map(|mask| format ^ mask);
+    for i in 0..18 {
+        let (x, y) = if i < 15 { (8 + i / 5, 1 + i % 5) } else { (11, i - 14) };
+        set(x, y, main >> i & 1 == 1, Function::Format);
+        let (x, y) = if i < 15 { (w - 8 + i / 5, h - 6 + i % 5) } else { (w - 5 + i - 15, h - 6) };
+        set(x, y, sub >> i & 1 == 1, Function::Format);
+    }
+
+    // Data.
+    let mut bits = codewords.iter().flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1));
+    let mut upward = true;
+    let mut x = w - 2;
+    while x > 0 {
+        for row in 0..h {
+            let y = if upward { h - 1 - row } else { row };
+            for xx in [x, x - 1] {
+                if functions[y * w + xx] == Function::Data {
+                    let mask = (y / 2 + xx / 3) % 2 == 0;
+                    modules[y * w + xx] = bits.next().unwrap_or(false) ^ mask;
+                }
+            }
+        }
+        upward = !upward;
+        x = x.saturating_sub(2);
+    }
+
+    Symbol::new(SymbolKind::RMQR, index as i16 + 1, ec, None, w, modules, functions)
+}
+
+#[cfg(test)]
+mod tests {
+    //! Reference symbols from the zxing-cpp rMQR decoder tests, which hold a
+    //! single alphanumeric segment like this encoder writes.
+
+    use super::*;
+
+    /// Rows of `symbol`, with `X` for dark modules.
+    fn rows(symbol: &Symbol) -> Vec<String> {
+        (0..symbol.height)
+            .map(|y| (0..symbol.width).map(|x| if symbol.is_dark(x, y) { 'X' } else { ' ' }).collect())
+            .collect()
+    }
+
+    #[test]
+    fn r7x43_m() {
+        let symbol = encode(b"ABCDEFG", EcLevel::M, 1, 1).unwrap();
+        let expected = [
+            "XXXXXXX X X X X X X XXX X X X X X X X X XXX",
+            "X     X  X XXX  XXXXX XXX      X X XX   X X",
+            "X XXX X X XXX X X X XXXX XXXX X  X XXXXXXXX",
+            "X XXX X  XX    XXXXX   XXXXXX   X X   X   X",
+            "X XXX X   XX  XXX   XXXXXXX  X X  XX  X X X",
+            "X     X XXXXX XXX XXX XXXXX    XXXXXX X   X",
+            "XXXXXXX X X X X X X XXX X X X X X X X XXXXX",
+        ];
+        assert_eq!(rows(&symbol), expected);
+    }
+
+    #[test]
+    fn r9x59_h() {
+        let symbol = encode(b"ABCDEFGHIJKLMN", EcLevel::H, 7, 7).unwrap();
+        let expected = [
+            "XXXXXXX X X X X X XXX X X X X X X X X XXX X X X X X X X XXX",
+            "X     X    X  XXXXX XXX X  X XXXXXXXX X X  X    X XXXX  X X",
+            "X XXX X XX XXX  X XXX XXXX  X         XXXXXXX  X XXXXX X  X",
+            "X XXX X XXXX X XX X   XX   XXXX XX  XX   X  X  X XXX     X ",
+            "X XXX X    X    X XX XXXXXX X X XX   X XX   X X XXXX  XXXXX",
+            "X     X X  X  X  X  XXX X X   X   XX  X XXXX XX  X X  X   X",
+            "XXXXXXX  XXXXX  XXXXXX X XX XXX X    XXXX  X    X  X XX X X",
+            "          XXX  XXXX XX XXX    X XXXXXXX X XX XXX  XX XX   X",
+            "XXX X X X X X X X XXX X X X X X X X X XXX X X X X X X XXXXX",
+        ];
+        assert_eq!(rows(&symbol), expected);
+    }
+
+    #[test]
+    fn r11x27_h() {
+        let symbol = encode(b"ABCDEF", EcLevel::H, 11, 11).unwrap();
+        let expected = [
+            "XXXXXXX X X X X X X X X XXX",
+            "X     X  XX        X  X X X",
+            "X XXX X    X  XX X   X   XX",
+            "X XXX X XXXX XX X  XXXXXX  ",
+            "X XXX X  X X XX  XX   XXX X",
+            "X     X XXX  X XX  XXXX  X ",
+            "XXXXXXX     X   XX  X XXXXX",
+            "           X   X   X  X   X",
+            "XXXX  X   X X XX XXXXXX X X",
+            "X XX XXXXXX XXX  XXXX X   X",
+            "XXX X X X X X X X X X XXXXX",
+        ];
+        assert_eq!(rows(&symbol), expected);
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt::Write;
+
+use crate::options::EyeShape;
+use crate::pdf;
+
+/// Samples per pixel along each axis when working out how much of a pixel a
+/// shape covers.
+const SAMPLES: usize = 4;
+
+/// Corner radius of rounded modules, relative to their side.
+pub const ROUNDED_RADIUS: f64 = 0.25;
+
+/// Outline of a single module, drawn within its square.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Shape {
+    /// A square whose corners are rounded by the given radii, relative to its
+    /// side and up to half of it, clockwise from the top-left.
+    Rounded([f64; 4]),
+    Diamond,
+}
+
+impl Shape {
+    pub const SQUARE: Shape = Shape::Rounded([0.0; 4]);
+    pub const CIRCLE: Shape = Shape::Rounded([0.5; 4]);
+
+    /// Whether the point at `x`, `y`, from 0 to 1 across the module, is inside
+    /// the shape.
+    fn contains(self, x: f64, y: f64) -> bool {
+        match self {
+            Shape::Rounded(radii) => {
+                // Distances to the nearest corner, and how round it is.
+                let (dx, dy, r) = match (x < 0.5, y < 0.5) {
+                    (true, true) => (x, y, radii[0]),
+                    (false, true) => (1.0 - x, y, radii[1]),
+                    (false, false) => (1.0 - x, 1.0 - y, radii[2]),
+                    (true, false) => (x, 1.0 - y, radii[3]),
+                };
+                dx >= r || dy >= r || (r - dx).powi(2) + (r - dy).powi(2) <= r * r
+            }
+            Shape::Diamond => (x - 0.5).abs() + (y - 0.5).abs() <= 0.5,
+        }
+    }
+
+    /// Whether the point at `x`, `y` is inside the shape when it is drawn
+    /// `side` wide from `offset`, `offset`.
+    fn contains_at(self, x: f64, y: f64, offset: f64, side: f64) -> bool {
+        let (x, y) = ((x - offset) / side, (y - offset) / side);
+        (0.0..1.0).contains(&x) && (0.0..1.0).contains(&y) && self.contains(x, y)
+    }
+
+    /// Share of the pixel at `x`, `y` within a module `size` pixels wide that
+    /// the shape covers, from 0 to 1.
+    pub fn coverage(self, x: usize, y: usize, size: usize) -> f32 {
+        match self {
+            Shape::SQUARE => 1.0,
+            _ => sample(x, y, size, |x, y| self.contains(x, y)),
+        }
+    }
+
+    /// Appends the outline as SVG path data, for a module `size` pixels wide
+    /// whose top-left pixel is at `left`, `top`.
+    pub fn path(self, d: &mut String, left: usize, top: usize, size: usize) {
+        let (x, y, s) = (left as f64, top as f64, size as f64);
+        let n = pdf::number;
+        match self {
+            _ if self == Shape::SQUARE => write!(d, "M{left} {top}h{size}v{size}H{left}V{top}").unwrap(),
+            Shape::Rounded(radii) => {
+                let [tl, tr, br, bl] = radii.map(|r| r * s);
+                write!(d, "M{} {}H{}", n(x + tl), n(y), n(x + s - tr)).unwrap();
+                arc(d, tr, x + s, y + tr);
+                write!(d, "V{}", n(y + s - br)).unwrap();
+                arc(d, br, x + s - br, y + s);
+                write!(d, "H{}", n(x + bl)).unwrap();
+                arc(d, bl, x, y + s - bl);
+                write!(d, "V{}", n(y + tl)).unwrap();
+                arc(d, tl, x + tl, y);
+                d.push('Z');
+            }
+            Shape::Diamond => {
+                let (cx, cy) = (n(x + s / 2.0), n(y + s / 2.0));
+                write!(d, "M{cx} {}L{} {cy}L{cx} {}L{} {cy}Z", n(y), n(x + s), n(y + s), n(x)).unwrap();
+            }
+        }
+    }
+}
+
+/// Share of the pixel at `x`, `y` where `inside` holds, with points measured
+/// in units of `unit` pixels.
+fn sample(x: usize, y: usize, unit: usize, inside: impl Fn(f64, f64) -> bool) -> f32 {
+    let point = |i: usize, pixel: usize| (pixel as f64 + (i as f64 + 0.5) / SAMPLES as f64) / unit as f64;
+    let count = (0..SAMPLES * SAMPLES).filter(|i| inside(point(i % SAMPLES, x), point(i / SAMPLES, y))).count();
+    count as f32 / (SAMPLES * SAMPLES) as f32
+}
+
+/// A finder pattern drawn as a whole rather than module by module: a ring one
+/// module thick, and a pupil two modules in from the outside.
+#[derive(Clone, Copy)]
+pub struct Eye {
+    /// Side of the pattern, in modules.
+    pub modules: usize,
+    outer: Shape,
+    inner: Shape,
+    pupil: Shape,
+}
+
+impl Eye {
+    pub fn new(frame: EyeShape, pupil: EyeShape, modules: usize) -> Self {
+        let n = modules as f64;
+        // The inner edge of a rounded ring follows the outer edge a module in.
+        let (outer, inner) = match frame {
+            EyeShape::Square | EyeShape::Diamond => (Shape::SQUARE, Shape::SQUARE),
+            EyeShape::Circle => (Shape::CIRCLE, Shape::CIRCLE),
+            EyeShape::Rounded => (
+                Shape::Rounded([ROUNDED_RADIUS; 4]),
+                Shape::Rounded([(ROUNDED_RADIUS * n - 1.0) / (n - 2.0); 4]),
+            ),
+        };
+        let pupil = match pupil {
+            EyeShape::Square => Shape::SQUARE,
+            EyeShape::Rounded => Shape::Rounded([ROUNDED_RADIUS; 4]),
+            EyeShape::Circle => Shape::CIRCLE,
+            EyeShape::Diamond => Shape::Diamond,
+        };
+
+        Eye {
+            modules,
+            outer,
+            inner,
+            pupil,
+        }
+    }
+
+    /// Shares of the pixel at `x`, `y`, counted from the top-left of the
+    /// pattern, that the ring and the pupil cover when modules are `size`
+    /// pixels wide.
+    pub fn coverage(&self, x: usize, y: usize, size: usize) -> (f32, f32) {
+        let n = self.modules as f64;
+        let ring = sample(x, y, size, |x, y| {
+            self.outer.contains_at(x, y, 0.0, n) && !self.inner.contains_at(x, y, 1.0, n - 2.0)
+        });
+        let pupil = sample(x, y, size, |x, y| self.pupil.contains_at(x, y, 2.0, n - 4.0));
+        (ring, pupil)
+    }
+
+    /// Appends the outer and inner edges of the ring as SVG path data, to be
+    /// filled with the even-odd rule.
+    pub fn ring_path(&self, d: &mut String, left: usize, top: usize, size: usize) {
+        self.outer.path(d, left, top, self.modules * size);
+        self.inner.path(d, left + size, top + size, (self.modules - 2) * size);
+    }
+
+    /// Appends the outline of the pupil as SVG path data.
+    pub fn pupil_path(&self, d: &mut String, left: usize, top: usize, size: usize) {
+        self.pupil.path(d, left + 2 * size, top + 2 * size, (self.modules - 4) * size);
+    }
+}
+
+/// Appends a clockwise quarter circle of radius `r` to `x`, `y`, unless the
+/// corner is square.
+fn arc(d: &mut String, r: f64, x: f64, y: f64) {
+    if r > 0.0 {
+        let n = pdf::number;
+        write!(d, "A{} {} 0 0 1 {} {}", n(r), n(r), n(x), n(y)).unwrap();
+    }
+}
@@ -0,0 +1,266 @@
+use qrcode::{Color, EcLevel, QrCode, Version};
+
+/// The family of 2D symbol to encode with.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    /// Regular QR code, versions 1 to 40.
+    QR,
+    /// Micro QR code, versions M1 to M4.
+    Micro,
+    /// Rectangular Micro QR code, versions R7x43 to R17x139.
+    RMQR,
+}
+
+impl SymbolKind {
+    pub const ALL: [SymbolKind; 3] = [SymbolKind::QR, SymbolKind::Micro, SymbolKind::RMQR];
+
+    /// Name, as accepted by `symbol`.
+    pub fn name(self) -> &'static str {
+        match self {
+            SymbolKind::QR => "qr",
+            SymbolKind::Micro => "micro",
+            SymbolKind::RMQR => "rmqr",
+        }
+    }
+    /// Number of versions of this kind. Versions are numbered from 1.
+    pub fn versions(self) -> i16 {
+        match self {
+            SymbolKind::QR => 40,
+            SymbolKind::Micro => 4,
+            SymbolKind::RMQR => 32,
+        }
+    }
+
+    /// Name of `version`, as printed in the specifications.
+    pub fn version_name(self, version: i16) -> String {
+        match self {
+            SymbolKind::QR => version.to_string(),
+            SymbolKind::Micro => format!("M{}", version),
+            SymbolKind::RMQR => {
+                let (height, width) = crate::rmqr::size(version);
+                format!("R{}x{}", height, width)
+            }
+        }
+    }
+
+    /// Width of the quiet zone required around the symbol, in modules.
+    pub fn quiet_zone(self) -> usize {
+        match self {
+            SymbolKind::QR => 4,
+            SymbolKind::Micro | SymbolKind::RMQR => 2,
+        }
+    }
+}
+
+/// What a module belongs to. Everything that is not data is a function
+/// pattern, drawn the same way in every symbol of a given version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Function {
+    Data,
+    /// Finder patterns, including the finder sub-pattern and the corner finder
+    /// patterns of rMQR.
+    Finder,
+    /// The light border around finder patterns.
+    Separator,
+    Timing,
+    Alignment,
+    /// Format information, including the dark module of QR codes.
+    Format,
+    /// Version information of QR codes from version 7.
+    Version,
+}
+
+impl Function {
+    pub const ALL: [Function; 7] = [
+        Function::Data,
+        Function::Finder,
+        Function::Separator,
+        Function::Timing,
+        Function::Alignment,
+        Function::Format,
+        Function::Version,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Function::Data => "data",
+            Function::Finder => "finder",
+            Function::Separator => "separator",
+            Function::Timing => "timing",
+            Function::Alignment => "alignment",
+            Function::Format => "format",
+            Function::Version => "version",
+        }
+    }
+}
+
+/// An encoded symbol, as a grid of dark and light modules.
+pub struct Symbol {
+    pub kind: SymbolKind,
+    /// Version, numbered from 1 as for the `version` option.
+    pub version: i16,
+    pub ec: EcLevel,
+    /// Data mask pattern, as numbered in the specification. rMQR has a single
+    /// fixed mask, and so no number.
+    pub mask: Option<u8>,
+    pub width: usize,
+    pub height: usize,
+    modules: Vec<bool>,
+    functions: Vec<Function>,
+}
+
+impl Symbol {
+    /// Builds a symbol from its modules and what each belongs to, in row-major
+    /// order with `true` for dark modules.
+    pub fn new(
+        kind: SymbolKind,
+        version: i16,
+        ec: EcLevel,
+        mask: Option<u8>,
+        width: usize,
+        modules: Vec<bool>,
+        functions: Vec<Function>,
+    ) -> Self {
+        Symbol {
+            kind,
+            version,
+            ec,
+            mask,
+            width,
+            height: modules.len() / width,
+            modules,
+            functions,
+        }
+    }
+
+    pub fn from_qrcode(kind: SymbolKind, code: &QrCode) -> Self {
+        let modules: Vec<bool> = code.to_colors().into_iter().map(|c| c == Color::Dark).collect();
+        let width = code.width();
+        let functions = (0..width * width).map(|i| qr_function(code.version(), width, i % width, i / width)).collect();
+
+        // The mask is read back from the first copy of the format information,
+        // which is stored most significant bit first.
+        let format = match code.version() {
+            Version::Normal(_) => FORMAT_QR.to_vec(),
+            Version::Micro(_) => (1..=8).map(|x| (x, 8)).chain((1..=7).rev().map(|y| (8, y))).collect(),
+        };
+        let format = format.iter().fold(0u16, |bits, &(x, y)| bits << 1 | modules[y * width + x] as u16);
+        let (version, mask) = match code.version() {
+            Version::Normal(version) => (version, ((format ^ 0x5412) >> 10 & 0b111) as u8),
+            Version::Micro(version) => (version, ((format ^ 0x4445) >> 10 & 0b11) as u8),
+        };
+
+        Symbol::new(kind, version, code.error_correction_level(), Some(mask), width, modules, functions)
+    }
+
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.width + x]
+    }
+
+    pub fn function(&self, x: usize, y: usize) -> Function {
+        self.functions[y * self.width + x]
+    }
+
+    /// Top-left module and side of every square finder pattern: three in QR
+    /// codes, one in Micro QR codes, and in rMQR the finder pattern and the
+    /// finder sub-pattern. The corner finder patterns of rMQR are not squares.
+    pub fn finder_patterns(&self) -> Vec<(usize, usize, usize)> {
+        match self.kind {
+            SymbolKind::QR => vec![(0, 0, 7), (self.width - 7, 0, 7), (0, self.height - 7, 7)],
+            SymbolKind::Micro => vec![(0, 0, 7)],
+            SymbolKind::RMQR => vec![(0, 0, 7), (self.width - 5, self.height - 5, 5)],
+        }
+    }
+}
+
+/// Positions of the first copy of the QR format information, from the most
+/// significant bit.
+const FORMAT_QR: [(usize, usize); 15] = [
+    (0, 8),
+    (1, 8),
+    (2, 8),
+    (3, 8),
+    (4, 8),
+    (5, 8),
+    (7, 8),
+    (8, 8),
+    (8, 7),
+    (8, 5),
+    (8, 4),
+    (8, 3),
+    (8, 2),
+    (8, 1),
+    (8, 0),
+];
+
+/// Centres of the alignment patterns of a QR code, along either axis.
+fn alignment_positions(version: i16, width: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+
+    let count = version as usize / 7 + 2;
+    let step = match version {
+        32 => 26,
+        _ => (version as usize * 4 + count * 2 + 1) / (count * 2 - 2) * 2,
+    };
+    let mut positions: Vec<usize> = (0..count - 1).map(|i| width - 7 - i * step).collect();
+    positions.push(6);
+    positions.reverse();
+    positions
+}
+
+/// Classifies the module at `x`, `y` of a QR or Micro QR code.
+fn qr_function(version: Version, width: usize, x: usize, y: usize) -> Function {
+    let near = |cx: usize, cy: usize, radius: usize| x.abs_diff(cx) <= radius && y.abs_diff(cy) <= radius;
+
+    match version {
+        Version::Micro(_) => match (x, y) {
+            _ if near(3, 3, 3) => Function::Finder,
+            (0..=7, 0..=7) => Function::Separator,
+            (0, _) | (_, 0) => Function::Timing,
+            (1..=8, 8) | (8, 1..=7) => Function::Format,
+            _ => Function::Data,
+        },
+        Version::Normal(version) => {
+            let far = width - 1;
+            let finders = [(3, 3), (far - 3, 3), (3, far - 3)];
+
+            // Alignment patterns are everywhere on the grid of positions, except
+            // where they would overlap the finder patterns.
+            let positions = alignment_positions(version, width);
+            let (first, last) = (positions.first().copied(), positions.last().copied());
+            let alignment = positions
+                .iter()
+                .flat_map(|&cx| positions.iter().map(move |&cy| (cx, cy)))
+                .filter(|&(cx, cy)| {
+                    !((Some(cx) == first && Some(cy) == first)
+                        || (Some(cx) == first && Some(cy) == last)
+                        || (Some(cx) == last && Some(cy) == first))
+                })
+                .any(|(cx, cy)| near(cx, cy, 2));
+
+            if finders.iter().any(|&(cx, cy)| near(cx, cy, 3)) {
+                Function::Finder
+            } else if finders.iter().any(|&(cx, cy)| near(cx, cy, 4)) {
+                Function::Separator
+            } else if (x == 8 && (y <= 8 || y >= far - 7)) || (y == 8 && (x <= 8 || x >= far - 7)) {
+                match x == 6 || y == 6 {
+                    true => Function::Timing,
+                    false => Function::Format,
+                }
+            } else if version >= 7
+                && (((far - 10..=far - 8).contains(&x) && y < 6) || ((far - 10..=far - 8).contains(&y) && x < 6))
+            {
+                Function::Version
+            } else if alignment {
+                Function::Alignment
+            } else if x == 6 || y == 6 {
+                Function::Timing
+            } else {
+                Function::Data
+            }
+        }
+    }
+}